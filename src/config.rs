@@ -0,0 +1,414 @@
+//! The configuration file and the patterns derived from it.
+
+use crate::document::write_atomic;
+use crate::error::TodoError;
+use crate::vault::vault_files;
+use crate::view::{builtin_views, ViewConfig};
+use crate::workspace::expand_glob;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Values accepted for `TodoFormatting.checkbox_style`, anything else is treated as "md".
+pub const CHECKBOX_STYLES: [&str; 2] = ["md", "logseq"];
+
+/// A date, optionally with a time and an offset: `2024-07-05`, `2024-07-05T14:00`, `2024-07-05 14:00+02:00`.
+const DATE_PATTERN: &str =
+    r"\d{4}-\d{2}-\d{2}(?:[T ]\d{2}:\d{2}(?::\d{2})?(?:Z|[+-]\d{2}(?::?\d{2})?)?)?";
+
+/// Name of a project-local config file, see `discover`.
+pub const LOCAL_CONFIG: &str = ".todo.toml";
+/// Name of a project-local todo file, see `discover`.
+pub const LOCAL_TODO_FILE: &str = "TODO.md";
+
+/// The `[path]` table: where the todo files are.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct TodoPath {
+    /// Directory of the todo file. Relative paths start at the directory of the config file.
+    pub todo_path: PathBuf,
+    /// Name of the todo file within `todo_path`.
+    pub todo_filename: PathBuf,
+    /// More files or glob patterns, relative to `todo_path`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+    /// Include every note in this directory, relative to `todo_path`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vault: Option<PathBuf>,
+}
+impl Default for TodoPath {
+    fn default() -> TodoPath {
+        TodoPath {
+            todo_path: PathBuf::new(),
+            todo_filename: "todo.md".into(),
+            files: Vec::new(),
+            vault: None,
+        }
+    }
+}
+
+/// The `[format]` table: how tasks are written.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct TodoFormatting {
+    /// One of `CHECKBOX_STYLES`: `- [ ]` for "md", `- TODO` for "logseq".
+    pub checkbox_style: String,
+}
+impl Default for TodoFormatting {
+    fn default() -> TodoFormatting {
+        TodoFormatting {
+            checkbox_style: "md".to_string(),
+        }
+    }
+}
+
+/// The `[behavior]` table: what the commands do.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct TodoBehavior {
+    /// Ask before removing each task.
+    pub confirm_remove: bool,
+    /// Add `✅ YYYY-MM-DD` when checking off a task, remove it when unchecking.
+    pub stamp_done_date: bool,
+}
+impl Default for TodoBehavior {
+    fn default() -> TodoBehavior {
+        TodoBehavior {
+            confirm_remove: true,
+            stamp_done_date: false,
+        }
+    }
+}
+
+/// The configuration file as it is written in TOML.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ConfigFile {
+    /// Where the todo files are.
+    #[serde(default)]
+    pub path: TodoPath,
+    /// How tasks are written.
+    #[serde(default)]
+    pub format: TodoFormatting,
+    /// What the commands do.
+    #[serde(default)]
+    pub behavior: TodoBehavior,
+    /// Saved queries by name, on top of the built-in ones, see `views`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub views: BTreeMap<String, ViewConfig>,
+}
+impl Default for ConfigFile {
+    fn default() -> ConfigFile {
+        ConfigFile {
+            format: TodoFormatting::default(),
+            path: TodoPath {
+                todo_path: dirs::home_dir().unwrap_or_default(),
+                todo_filename: "todo.md".into(),
+                files: Vec::new(),
+                vault: None,
+            },
+            behavior: TodoBehavior::default(),
+            views: BTreeMap::new(),
+        }
+    }
+}
+impl ConfigFile {
+    /// Where the configuration is expected, usually `~/.config/todo-md-rs/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("todo-md-rs").join("config.toml"))
+    }
+
+    /// Reads the config at `path`. A relative `todo_path` is resolved against its directory.
+    pub fn load(path: &Path) -> Result<ConfigFile, TodoError> {
+        let contents: String = fs::read_to_string(path).map_err(TodoError::io(path))?;
+        let mut conf: ConfigFile = toml::from_str(&contents).map_err(|e| TodoError::Config {
+            path: path.to_path_buf(),
+            message: e.message().to_string(),
+        })?;
+        if let Some(config_dir) = path.parent() {
+            conf.path.todo_path = config_dir.join(&conf.path.todo_path);
+        }
+        Ok(conf)
+    }
+
+    /// Writes the config to `path`, creating its directory if needed.
+    pub fn save(&self, path: &Path) -> Result<(), TodoError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(TodoError::io(parent))?;
+        }
+        write_atomic(path, &toml::to_string(self).unwrap())
+    }
+
+    /// The complete path to the todo file.
+    pub fn todo_file(&self) -> PathBuf {
+        self.path.todo_path.join(&self.path.todo_filename)
+    }
+
+    /// The complete path to the vault, if there is one.
+    pub fn vault_dir(&self) -> Option<PathBuf> {
+        self.path
+            .vault
+            .as_ref()
+            .map(|vault| self.path.todo_path.join(vault))
+    }
+
+    /// The todo file followed by all files matching `TodoPath.files` and all notes of the vault,
+    /// without duplicates. Patterns may match nothing, but plain paths have to be existing files.
+    pub fn todo_files(&self) -> Result<Vec<PathBuf>, TodoError> {
+        let mut files: Vec<PathBuf> = vec![self.todo_file()];
+        for pattern in &self.path.files {
+            let found = expand_glob(&self.path.todo_path, pattern);
+            if found.is_empty() && !pattern.contains(['*', '?']) {
+                let path = self.path.todo_path.join(pattern);
+                let error = match fs::metadata(&path) {
+                    Ok(_) => io::ErrorKind::IsADirectory.into(),
+                    Err(e) => e,
+                };
+                return Err(TodoError::io(path)(error));
+            }
+            files.extend(found);
+        }
+        if let Some(vault) = self.vault_dir() {
+            files.extend(vault_files(&vault)?);
+        }
+
+        let mut seen: Vec<PathBuf> = Vec::new();
+        files.retain(|file| {
+            let canonical = fs::canonicalize(file).unwrap_or_else(|_| file.clone());
+            let is_new = !seen.contains(&canonical);
+            seen.push(canonical);
+            is_new
+        });
+        Ok(files)
+    }
+
+    /// The built-in views together with the configured ones, which take precedence.
+    pub fn views(&self) -> BTreeMap<String, ViewConfig> {
+        let mut views = builtin_views();
+        views.extend(self.views.clone());
+        views
+    }
+
+    /// Points the configuration to another todo file, which is then the only one used.
+    pub fn set_todo_file(&mut self, todo_file: &Path) {
+        self.path.files.clear();
+        self.path.vault = None;
+        let todo_file = std::path::absolute(todo_file).unwrap_or_else(|_| todo_file.to_path_buf());
+        self.path.todo_filename = todo_file.file_name().unwrap_or_default().into();
+        self.path.todo_path = todo_file.parent().unwrap_or(Path::new("")).into();
+    }
+
+    /// Includes all notes in the directory `vault`.
+    pub fn set_vault(&mut self, vault: &Path) {
+        self.path.vault = Some(std::path::absolute(vault).unwrap_or_else(|_| vault.to_path_buf()));
+    }
+}
+
+/// A project-local configuration found by `discover`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Discovered {
+    /// a `.todo.toml` that replaces the global config
+    Config(PathBuf),
+    /// a `TODO.md` to use instead of the configured todo file
+    TodoFile(PathBuf),
+}
+
+/// Looks for a `.todo.toml` or `TODO.md` in `start` and all of its parents, like git finds `.git`.
+/// The closest one wins, a `.todo.toml` is preferred over a `TODO.md` in the same directory.
+pub fn discover(start: &Path) -> Option<Discovered> {
+    start.ancestors().find_map(|dir| {
+        let config = dir.join(LOCAL_CONFIG);
+        let todo_file = dir.join(LOCAL_TODO_FILE);
+        if config.is_file() {
+            Some(Discovered::Config(config))
+        } else if todo_file.is_file() {
+            Some(Discovered::TodoFile(todo_file))
+        } else {
+            None
+        }
+    })
+}
+
+/// The patterns tasks are parsed with, derived from the checkbox style of a `ConfigFile`.
+pub struct TodoConfig {
+    /// Checks whether a line is a task. Group 1 is the checkbox marker.
+    pub completion_style: Regex,
+    /// Checks whether a task is done.
+    pub completion_done: Regex,
+    /// A date without a marker. Group 1 is the date.
+    pub date_format: Regex,
+    /// A date of a certain kind, like `⏳ 2024-07-05` or `due:2024-07-05`. Group 1 is the marker, group 2 the date.
+    pub date_marker: Regex,
+    /// todo.txt completion and creation dates right after the checkbox.
+    pub leading_dates: Regex,
+    /// A `SCHEDULED:` or `DEADLINE:` line under a Logseq task.
+    pub logseq_date: Regex,
+    /// A trailing `^id`. Group 1 is the ID without the `^`.
+    pub stable_id: Regex,
+    /// A priority marker like `!!`, `(A)` or `⏫`. Group 1 is the marker.
+    pub priority_format: Regex,
+    /// A tag like `#work`.
+    pub tag_format: Regex,
+    /// A name like `@alice`.
+    pub name_format: Regex,
+    /// How a new task starts, e.g. `- [ ]`.
+    pub example_todo: String,
+    /// How a completed task starts, e.g. `- [X]`.
+    pub example_done: String,
+    /// The part of `example_todo` that changes when toggling.
+    pub checkbox_todo: String,
+    /// The part of `example_done` that changes when toggling.
+    pub checkbox_done: String,
+}
+impl TodoConfig {
+    /// The patterns for the checkbox style of `conf_file`, Markdown if it's unknown.
+    pub fn new(conf_file: &ConfigFile) -> TodoConfig {
+        let default_md: TodoConfig = TodoConfig {
+            completion_style: Regex::new(r"^\s*-\s*(\[[ xX]\])").unwrap(),
+            completion_done: Regex::new(r"^\s*-\s*\[[^\s]\]").unwrap(),
+            date_format: Regex::new(&format!(r"(?:^|\s)({DATE_PATTERN})(?:\s|$)")).unwrap(),
+            date_marker: Regex::new(&format!(
+                r"(?:^|\s)(📅|⏳|🛫|➕|✅|due:|t:) ?({DATE_PATTERN})(?:\s|$)"
+            ))
+            .unwrap(),
+            leading_dates: Regex::new(r"^\s*(\d{4}-\d{2}-\d{2})(?:\s+(\d{4}-\d{2}-\d{2}))?(?:\s|$)")
+                .unwrap(),
+            logseq_date: Regex::new(
+                r"^\s*(SCHEDULED|DEADLINE):\s*<(\d{4}-\d{2}-\d{2})(?:\s+[A-Za-z]+)?(?:\s+(\d{2}:\d{2}))?",
+            )
+            .unwrap(),
+            stable_id: Regex::new(r"(?:^|\s)\^([a-z0-9]+)\s*$").unwrap(),
+            priority_format: Regex::new(r"(?:^|\s)(!{1,3}|\([A-Z]\)|🔺|⏫|🔼|🔽|⏬)(?:\s|$)")
+                .unwrap(),
+            tag_format: Regex::new(r"#\w+").unwrap(),
+            name_format: Regex::new(r"@\w+").unwrap(),
+            example_todo: String::from("- [ ]"),
+            example_done: String::from("- [X]"),
+            checkbox_todo: String::from("[ ]"),
+            checkbox_done: String::from("[X]"),
+        };
+
+        // Markdown style
+        if conf_file.format.checkbox_style == "md" {
+            default_md
+        }
+        // Logseq style
+        else if conf_file.format.checkbox_style == "logseq" {
+            TodoConfig {
+                completion_style: Regex::new(r"^\s*-\s*([A-Z]{4})").unwrap(),
+                completion_done: Regex::new(r"^\s*-\s*DONE\s").unwrap(),
+                date_format: default_md.date_format,
+                date_marker: default_md.date_marker,
+                leading_dates: default_md.leading_dates,
+                logseq_date: default_md.logseq_date,
+                stable_id: default_md.stable_id,
+                priority_format: default_md.priority_format,
+                tag_format: default_md.tag_format,
+                name_format: default_md.name_format,
+                example_todo: String::from("- TODO"),
+                example_done: String::from("- DONE"),
+                checkbox_todo: String::from("TODO"),
+                checkbox_done: String::from("DONE"),
+            }
+        }
+        // default to Markdown
+        else {
+            default_md
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn plain_files_have_to_exist() {
+        let dir = TempDir::new("config-files");
+        dir.write("todo.md", "");
+        dir.write("work.md", "");
+        let mut conf_file = ConfigFile::default();
+        conf_file.path.todo_path = dir.path().to_path_buf();
+
+        conf_file.path.files = vec!["work.md".to_string(), "journal/*.md".to_string()];
+        assert_eq!(
+            conf_file.todo_files().unwrap(),
+            [dir.path().join("todo.md"), dir.path().join("work.md")]
+        );
+
+        conf_file.path.files = vec!["wrok.md".to_string()];
+        assert!(matches!(
+            conf_file.todo_files(),
+            Err(TodoError::Io { path, .. }) if path == dir.path().join("wrok.md")
+        ));
+    }
+
+    #[test]
+    fn closest_local_file_wins() {
+        let dir = TempDir::new("config-discover");
+        let project = dir.path().join("project");
+        let nested = project.join("src/nested");
+        fs::create_dir_all(&nested).unwrap();
+
+        dir.write(LOCAL_CONFIG, "");
+        assert_eq!(
+            discover(&nested),
+            Some(Discovered::Config(dir.path().join(LOCAL_CONFIG)))
+        );
+
+        // closer than the `.todo.toml` above
+        let todo_file = dir.write("project/TODO.md", "");
+        assert_eq!(discover(&nested), Some(Discovered::TodoFile(todo_file)));
+
+        // preferred over the `TODO.md` next to it
+        let config = dir.write("project/.todo.toml", "");
+        assert_eq!(discover(&nested), Some(Discovered::Config(config)));
+        assert_eq!(
+            discover(&project),
+            Some(Discovered::Config(project.join(LOCAL_CONFIG)))
+        );
+    }
+
+    #[test]
+    fn relative_todo_path_starts_at_the_config() {
+        let dir = TempDir::new("config-load");
+        let relative = dir.write(
+            "project/.todo.toml",
+            "[path]\ntodo_path = \"notes\"\ntodo_filename = \"tasks.md\"\n",
+        );
+        let conf_file = ConfigFile::load(&relative).unwrap();
+        assert_eq!(
+            conf_file.todo_file(),
+            dir.path().join("project/notes/tasks.md")
+        );
+
+        // no `todo_path` is the directory of the config itself
+        let missing = dir.write(
+            "other/.todo.toml",
+            "[format]\ncheckbox_style = \"logseq\"\n",
+        );
+        let conf_file = ConfigFile::load(&missing).unwrap();
+        assert_eq!(conf_file.todo_file(), dir.path().join("other/todo.md"));
+        assert_eq!(conf_file.format.checkbox_style, "logseq");
+
+        let absolute = dir.write(
+            "third/.todo.toml",
+            &format!("[path]\ntodo_path = {:?}\n", dir.path().join("elsewhere")),
+        );
+        let conf_file = ConfigFile::load(&absolute).unwrap();
+        assert_eq!(conf_file.todo_file(), dir.path().join("elsewhere/todo.md"));
+    }
+
+    #[test]
+    fn invalid_config_is_reported() {
+        let dir = TempDir::new("config-invalid");
+        let path = dir.write(".todo.toml", "[path\n");
+        assert!(matches!(
+            ConfigFile::load(&path),
+            Err(TodoError::Config { path: reported, .. }) if reported == path
+        ));
+    }
+}
@@ -0,0 +1,380 @@
+//! Reading dates the way people write them, like `fri`, `+3d` or `end of month`.
+
+use crate::error::TodoError;
+use chrono::{
+    DateTime, Datelike, Days, FixedOffset, Local, Months, NaiveDate, NaiveDateTime, NaiveTime,
+    TimeDelta, Weekday,
+};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// When a task is due: a whole day, or a point in time that day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Due {
+    /// `2024-07-05`
+    Date(NaiveDate),
+    /// `2024-07-05T14:00` or `2024-07-05 14:00`, in local time
+    DateTime(NaiveDateTime),
+    /// `2024-07-05T14:00+02:00` or `2024-07-05T12:00Z`, the same moment everywhere
+    Zoned(DateTime<FixedOffset>),
+}
+impl Due {
+    /// Reads a due date as it is written in a task line, see the variants.
+    pub fn parse(input: &str) -> Option<Due> {
+        if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+            return Some(Due::Date(date));
+        }
+        let input = input.replacen(' ', "T", 1);
+        let input = match input.strip_suffix('Z') {
+            Some(utc) => format!("{utc}+00:00"),
+            None => input,
+        };
+        for format in ["%Y-%m-%dT%H:%M%#z", "%Y-%m-%dT%H:%M:%S%#z"] {
+            if let Ok(zoned) = DateTime::parse_from_str(&input, format) {
+                return Some(Due::Zoned(zoned));
+            }
+        }
+        for format in ["%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"] {
+            if let Ok(local) = NaiveDateTime::parse_from_str(&input, format) {
+                return Some(Due::DateTime(local));
+            }
+        }
+        None
+    }
+
+    /// The local day it's due.
+    pub fn date(&self) -> NaiveDate {
+        match self {
+            Due::Date(date) => *date,
+            Due::DateTime(local) => local.date(),
+            Due::Zoned(zoned) => zoned.with_timezone(&Local).date_naive(),
+        }
+    }
+
+    /// The same time of day, `days` later.
+    pub fn add_days(&self, days: i64) -> Option<Due> {
+        let days = TimeDelta::try_days(days)?;
+        match self {
+            Due::Date(date) => date.checked_add_signed(days).map(Due::Date),
+            Due::DateTime(local) => local.checked_add_signed(days).map(Due::DateTime),
+            Due::Zoned(zoned) => zoned.checked_add_signed(days).map(Due::Zoned),
+        }
+    }
+
+    /// The local time it's due, if it's not the whole day.
+    pub fn local_time(&self) -> Option<NaiveDateTime> {
+        match self {
+            Due::Date(_) => None,
+            Due::DateTime(local) => Some(*local),
+            Due::Zoned(zoned) => Some(zoned.with_timezone(&Local).naive_local()),
+        }
+    }
+}
+
+/// Earlier first, a whole day comes after all times of that day.
+impl Ord for Due {
+    fn cmp(&self, other: &Due) -> Ordering {
+        let key = |due: &Due| (due.date(), due.local_time().is_none(), due.local_time());
+        key(self).cmp(&key(other))
+    }
+}
+impl PartialOrd for Due {
+    fn partial_cmp(&self, other: &Due) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Written the way `parse` reads it, seconds only if there are any.
+impl fmt::Display for Due {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time_format = |time: NaiveTime| match time.format("%S").to_string().as_str() {
+            "00" => "%Y-%m-%dT%H:%M",
+            _ => "%Y-%m-%dT%H:%M:%S",
+        };
+        match self {
+            Due::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            Due::DateTime(local) => write!(f, "{}", local.format(time_format(local.time()))),
+            Due::Zoned(zoned) => {
+                let format = format!("{}%:z", time_format(zoned.time()));
+                write!(f, "{}", zoned.format(&format))
+            }
+        }
+    }
+}
+
+/// Like `parse_date`, optionally followed by a time: `fri 14:00`, `tomorrow 9:30`.
+/// Whatever `Due::parse` reads is accepted as well.
+pub fn parse_due(input: &str, today: NaiveDate) -> Result<Due, TodoError> {
+    if let Some(due) = Due::parse(input.trim()) {
+        return Ok(due);
+    }
+    if let Some((day, time)) = input.trim().rsplit_once(' ') {
+        if let Ok(time) = NaiveTime::parse_from_str(time, "%H:%M") {
+            let date =
+                parse_date(day, today).map_err(|_| TodoError::InvalidDate(input.to_string()))?;
+            return Ok(Due::DateTime(date.and_time(time)));
+        }
+    }
+    parse_date(input, today).map(Due::Date)
+}
+
+/// Resolves `input` against `today`. Accepted are `YYYY-MM-DD` and
+/// - `today`, `tomorrow`, `yesterday`
+/// - a weekday like `fri` or `friday`, always the next one after today
+/// - `next week` (its Monday), `next month` and `next year` (their first day)
+/// - `+3d`, `-1w`, `+2m`, `+1y` and `in 3 days`, `in 2 weeks`, `in 1 month`, `in 1 year`
+/// - `end of week` (Sunday), `end of month` and `end of year`
+pub fn parse_date(input: &str, today: NaiveDate) -> Result<NaiveDate, TodoError> {
+    let invalid = || TodoError::InvalidDate(input.to_string());
+    let normalized = input
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase();
+
+    match normalized.as_str() {
+        "today" => return Ok(today),
+        "tomorrow" => return Ok(today + Days::new(1)),
+        "yesterday" => return Ok(today - Days::new(1)),
+        "next week" => return Ok(start_of_week(today) + Days::new(7)),
+        "next month" => return first_of_month(today, 1).ok_or_else(invalid),
+        "next year" => return NaiveDate::from_ymd_opt(today.year() + 1, 1, 1).ok_or_else(invalid),
+        "end of week" => return Ok(start_of_week(today) + Days::new(6)),
+        "end of month" => {
+            return first_of_month(today, 1)
+                .and_then(|first| first.pred_opt())
+                .ok_or_else(invalid)
+        }
+        "end of year" => return NaiveDate::from_ymd_opt(today.year(), 12, 31).ok_or_else(invalid),
+        _ => (),
+    }
+
+    if let Ok(weekday) = normalized.parse::<Weekday>() {
+        let days_ahead =
+            (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+        return Ok(today
+            + Days::new(if days_ahead == 0 {
+                7
+            } else {
+                days_ahead.into()
+            }));
+    }
+
+    if let Some(offset) = parse_offset(&normalized) {
+        return offset.apply(today).ok_or_else(invalid);
+    }
+
+    NaiveDate::parse_from_str(input.trim(), "%Y-%m-%d").map_err(|_| invalid())
+}
+
+/// An amount of time to move a date by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Offset {
+    Days(i64),
+    Months(i32),
+}
+impl Offset {
+    fn apply(self, date: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Offset::Days(days) if days < 0 => date.checked_sub_days(Days::new(days.unsigned_abs())),
+            Offset::Days(days) => date.checked_add_days(Days::new(days as u64)),
+            Offset::Months(months) if months < 0 => {
+                date.checked_sub_months(Months::new(months.unsigned_abs()))
+            }
+            Offset::Months(months) => date.checked_add_months(Months::new(months as u32)),
+        }
+    }
+}
+
+/// `+3d`, `-2w`, `+1m`, `+1y` or `in 3 days`, `in 2 weeks`, ...
+fn parse_offset(input: &str) -> Option<Offset> {
+    let (sign, amount, unit) = if let Some(rest) = input.strip_prefix("in ") {
+        let (amount, unit) = rest.split_once(' ')?;
+        (1, amount, unit)
+    } else {
+        let sign = match input.chars().next()? {
+            '+' => 1,
+            '-' => -1,
+            _ => return None,
+        };
+        let rest = &input[1..];
+        let split = rest.find(|c: char| !c.is_ascii_digit())?;
+        (sign, &rest[..split], &rest[split..])
+    };
+    // too large to be a date anyway
+    let amount = amount.parse::<i32>().ok()?.checked_mul(sign)?;
+
+    match unit {
+        "d" | "day" | "days" => Some(Offset::Days(i64::from(amount))),
+        "w" | "week" | "weeks" => Some(Offset::Days(i64::from(amount) * 7)),
+        "m" | "month" | "months" => Some(Offset::Months(amount)),
+        "y" | "year" | "years" => amount.checked_mul(12).map(Offset::Months),
+        _ => None,
+    }
+}
+
+fn start_of_week(date: NaiveDate) -> NaiveDate {
+    date - Days::new(date.weekday().num_days_from_monday().into())
+}
+
+/// The first day of the month `months` after the one of `date`.
+fn first_of_month(date: NaiveDate, months: u32) -> Option<NaiveDate> {
+    date.with_day(1)?.checked_add_months(Months::new(months))
+}
+
+/// The dates a task can have besides being due.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateKind {
+    /// `📅 2024-07-05`, `due:2024-07-05`, Logseq `DEADLINE:` or just a date
+    Due,
+    /// `⏳ 2024-07-05` or Logseq `SCHEDULED:`, when it's planned to be worked on
+    Scheduled,
+    /// `🛫 2024-07-05` or todo.txt `t:2024-07-05`, when it can be started
+    Start,
+    /// `➕ 2024-07-05` or the todo.txt creation date
+    Created,
+    /// `✅ 2024-07-05` or the todo.txt completion date
+    Done,
+}
+impl DateKind {
+    /// The kind of date that follows `marker` in a task line.
+    pub fn from_marker(marker: &str) -> Option<DateKind> {
+        match marker {
+            "📅" | "due:" => Some(DateKind::Due),
+            "⏳" => Some(DateKind::Scheduled),
+            "🛫" | "t:" => Some(DateKind::Start),
+            "➕" => Some(DateKind::Created),
+            "✅" => Some(DateKind::Done),
+            _ => None,
+        }
+    }
+
+    /// The Obsidian Tasks emoji, used when writing a date of this kind.
+    pub fn marker(&self) -> &'static str {
+        match self {
+            DateKind::Due => "📅",
+            DateKind::Scheduled => "⏳",
+            DateKind::Start => "🛫",
+            DateKind::Created => "➕",
+            DateKind::Done => "✅",
+        }
+    }
+}
+
+impl fmt::Display for DateKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DateKind::Due => "due",
+            DateKind::Scheduled => "scheduled",
+            DateKind::Start => "start",
+            DateKind::Created => "created",
+            DateKind::Done => "done",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(input: &str) -> NaiveDate {
+        input.parse().unwrap()
+    }
+
+    // a Wednesday
+    const TODAY: &str = "2024-07-03";
+
+    #[test]
+    fn relative_dates() {
+        let today = date(TODAY);
+        for (input, expected) in [
+            ("today", "2024-07-03"),
+            ("Tomorrow", "2024-07-04"),
+            ("fri", "2024-07-05"),
+            ("wednesday", "2024-07-10"),
+            ("next week", "2024-07-08"),
+            ("next month", "2024-08-01"),
+            ("end of week", "2024-07-07"),
+            ("end  of  month", "2024-07-31"),
+            ("+3d", "2024-07-06"),
+            ("-1w", "2024-06-26"),
+            ("in 2 weeks", "2024-07-17"),
+            ("+1y", "2025-07-03"),
+            ("2024-12-24", "2024-12-24"),
+        ] {
+            assert_eq!(parse_date(input, today).unwrap(), date(expected), "{input}");
+        }
+        // the end of a shorter month
+        assert_eq!(
+            parse_date("+1m", date("2024-01-31")).unwrap(),
+            date("2024-02-29")
+        );
+    }
+
+    #[test]
+    fn invalid_dates() {
+        let today = date(TODAY);
+        for input in [
+            "someday",
+            "2024-13-01",
+            "+3x",
+            "+999999999y",
+            "+99999999999d",
+            "in a week",
+        ] {
+            assert!(
+                matches!(parse_date(input, today), Err(TodoError::InvalidDate(_))),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn offsets_dont_overflow() {
+        assert_eq!(parse_offset("+999999999y"), None);
+        assert_eq!(
+            parse_offset("-2147483647m"),
+            Some(Offset::Months(-2147483647))
+        );
+        assert_eq!(parse_offset("in 2 weeks"), Some(Offset::Days(14)));
+    }
+
+    #[test]
+    fn due_with_time() {
+        let today = date(TODAY);
+        assert_eq!(
+            parse_due("fri 14:00", today).unwrap(),
+            Due::DateTime(date("2024-07-05").and_hms_opt(14, 0, 0).unwrap())
+        );
+        assert_eq!(
+            parse_due("tomorrow", today).unwrap(),
+            Due::Date(date("2024-07-04"))
+        );
+        assert!(parse_due("fri 25:00", today).is_err());
+    }
+
+    #[test]
+    fn due_notations_round_trip() {
+        for (input, written) in [
+            ("2024-07-05", "2024-07-05"),
+            ("2024-07-05T14:00", "2024-07-05T14:00"),
+            ("2024-07-05 14:00", "2024-07-05T14:00"),
+            ("2024-07-05T14:00:30", "2024-07-05T14:00:30"),
+            ("2024-07-05T14:00+02:00", "2024-07-05T14:00+02:00"),
+            ("2024-07-05T12:00Z", "2024-07-05T12:00+00:00"),
+        ] {
+            assert_eq!(Due::parse(input).unwrap().to_string(), written, "{input}");
+        }
+        assert_eq!(Due::parse("2024-07-05T"), None);
+    }
+
+    #[test]
+    fn whole_day_sorts_after_times() {
+        let day = Due::parse("2024-07-05").unwrap();
+        let morning = Due::parse("2024-07-05T09:00").unwrap();
+        let evening = Due::parse("2024-07-05T21:00").unwrap();
+        let next_day = Due::parse("2024-07-06T08:00").unwrap();
+        assert!(morning < evening && evening < day && day < next_day);
+    }
+}
@@ -0,0 +1,512 @@
+//! Reading and writing todo files.
+
+use crate::config::TodoConfig;
+use crate::dates::{DateKind, Due};
+use crate::error::TodoError;
+use crate::item::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// In-memory copy of a todo file.
+/// All edits of one command are collected here and written back at once by `save`.
+/// Line numbers always refer to the file as it was loaded, no matter what was edited in between.
+pub struct TodoDocument {
+    /// Where the file was loaded from and is saved to.
+    pub path: PathBuf,
+    lines: Vec<String>,
+    replaced: BTreeMap<usize, String>, // keyed by the 1-indexed line number
+    removed: BTreeSet<usize>,
+    inserted: BTreeMap<usize, Vec<String>>, // new lines after the given line number, 0 is the very top
+    line_ending: &'static str,
+    trailing_newline: bool,
+    // to notice edits by other programs between loading and saving
+    loaded_hash: u64,
+    loaded_mtime: Option<SystemTime>,
+}
+impl TodoDocument {
+    /// Reads the file at `path`, remembering its line endings and whether it ends with a newline.
+    pub fn load(path: &Path) -> Result<TodoDocument, TodoError> {
+        let content = fs::read_to_string(path).map_err(TodoError::io(path))?;
+        let loaded_mtime = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        Ok(TodoDocument::from_content(path, &content, loaded_mtime))
+    }
+
+    fn from_content(path: &Path, content: &str, loaded_mtime: Option<SystemTime>) -> TodoDocument {
+        TodoDocument {
+            path: path.to_path_buf(),
+            lines: content.lines().map(String::from).collect(),
+            replaced: BTreeMap::new(),
+            removed: BTreeSet::new(),
+            inserted: BTreeMap::new(),
+            line_ending: if content.contains("\r\n") {
+                "\r\n"
+            } else {
+                "\n"
+            },
+            trailing_newline: content.is_empty() || content.ends_with('\n'),
+            loaded_hash: content_hash(content),
+            loaded_mtime,
+        }
+    }
+
+    /// The lines as they were loaded.
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// All tasks of the file as it was loaded.
+    pub fn tasks(&self, conf_todo: &TodoConfig) -> Vec<TodoItem> {
+        strings_to_todo(self.lines.clone(), &self.path, conf_todo)
+    }
+
+    /// Checks or unchecks `item`, only touching the checkbox of its line.
+    pub fn set_completed(&mut self, item: &mut TodoItem, completed: bool, conf_todo: &TodoConfig) {
+        item.set_completed(completed, conf_todo);
+        self.set_line(item.line, item.raw.clone());
+    }
+
+    /// Writes the raw line of `item` back, after it was changed with `TodoItem::apply` or the like.
+    /// Its Logseq `SCHEDULED:` and `DEADLINE:` lines are updated or removed along with it.
+    pub fn update_task(&mut self, item: &TodoItem, conf_todo: &TodoConfig) {
+        self.set_line(item.line, item.raw.clone());
+
+        // like when parsing, only the first line of each kind counts
+        let mut seen: Vec<DateKind> = Vec::new();
+        for line_nr in self.logseq_lines_after(item.line, conf_todo) {
+            let line = &self.lines[line_nr - 1];
+            let Some((kind, written, span)) = parse_logseq_date(line, conf_todo) else {
+                continue;
+            };
+            if seen.contains(&kind) {
+                continue;
+            }
+            seen.push(kind);
+            if item.has_inline_date(kind) {
+                continue;
+            }
+            let date = match kind {
+                DateKind::Due => item.date_due,
+                _ => item.date_scheduled.map(Due::Date),
+            };
+            match date {
+                None => self.remove_line(line_nr),
+                Some(date) if date == written => (),
+                Some(date) => {
+                    let mut line = line.clone();
+                    line.replace_range(span, &logseq_date_text(date));
+                    self.set_line(line_nr, line);
+                }
+            }
+        }
+    }
+
+    /// Removes the line of `item`, together with its Logseq `SCHEDULED:` and `DEADLINE:` lines.
+    pub fn remove_task(&mut self, item: &TodoItem, conf_todo: &TodoConfig) {
+        self.remove_line(item.line);
+        for line_nr in self.logseq_lines_after(item.line, conf_todo) {
+            self.remove_line(line_nr);
+        }
+    }
+
+    /// Line numbers of the Logseq `SCHEDULED:` and `DEADLINE:` lines right below line `line_nr`.
+    fn logseq_lines_after(&self, line_nr: usize, conf_todo: &TodoConfig) -> Range<usize> {
+        let count = self
+            .lines
+            .iter()
+            .skip(line_nr)
+            .take_while(|line| conf_todo.logseq_date.is_match(line))
+            .count();
+        line_nr + 1..line_nr + 1 + count
+    }
+
+    /// Adds `item` to the end of the file, or to the end of the heading called `section`.
+    /// Its line, positional ID and section are set to where it ends up.
+    pub fn add_task(
+        &mut self,
+        item: &mut TodoItem,
+        section: Option<&str>,
+        conf_todo: &TodoConfig,
+    ) -> Result<(), TodoError> {
+        let line_nr = match section {
+            Some(name) => find_section_end(&self.lines, name)
+                .ok_or_else(|| TodoError::SectionNotFound(name.to_string()))?,
+            None => self.lines.len(),
+        };
+        self.insert_line(line_nr, item.raw.clone());
+        item.line = self.rendered_line_nr(line_nr);
+        item.id = self
+            .tasks(conf_todo)
+            .iter()
+            .filter(|other| other.line <= line_nr)
+            .count()
+            + 1;
+
+        // the headings it's under, like in `strings_to_todo`
+        let mut headings: Vec<(usize, String)> = Vec::new();
+        for (level, title) in self.lines[..line_nr]
+            .iter()
+            .filter_map(|line| parse_heading(line))
+        {
+            headings.retain(|(outer, _)| *outer < level);
+            headings.push((level, title));
+        }
+        item.section = headings.into_iter().map(|(_, title)| title).collect();
+        Ok(())
+    }
+
+    /// Inserts `item` right below `above`, after the Logseq `SCHEDULED:` or `DEADLINE:` lines that belong to it.
+    /// Its own dates from such lines are written below it the same way. Its line is set to where it ends up.
+    pub fn insert_task_after(
+        &mut self,
+        item: &mut TodoItem,
+        above: &TodoItem,
+        conf_todo: &TodoConfig,
+    ) {
+        let line_nr = self.logseq_lines_after(above.line, conf_todo).end - 1;
+        self.insert_line(line_nr, item.raw.clone());
+        item.line = self.rendered_line_nr(line_nr);
+        for line in item.logseq_lines() {
+            self.insert_line(line_nr, line);
+        }
+    }
+
+    /// Where the last line inserted after `line_nr` ends up once all edits are applied.
+    fn rendered_line_nr(&self, line_nr: usize) -> usize {
+        let kept = (1..=line_nr)
+            .filter(|kept| !self.removed.contains(kept))
+            .count();
+        let inserted: usize = self
+            .inserted
+            .range(..=line_nr)
+            .map(|(_, lines)| lines.len())
+            .sum();
+        kept + inserted
+    }
+
+    /// Checks whether there are edits that haven't been saved yet.
+    pub fn is_modified(&self) -> bool {
+        !(self.replaced.is_empty() && self.removed.is_empty() && self.inserted.is_empty())
+    }
+
+    /// Replaces line `line_nr` (1-indexed).
+    pub fn set_line(&mut self, line_nr: usize, line_content: String) {
+        if (1..=self.lines.len()).contains(&line_nr) {
+            self.replaced.insert(line_nr, line_content);
+        }
+    }
+
+    /// Removes line `line_nr` (1-indexed).
+    pub fn remove_line(&mut self, line_nr: usize) {
+        if (1..=self.lines.len()).contains(&line_nr) {
+            self.removed.insert(line_nr);
+        }
+    }
+
+    /// Inserts `line_content` after line `line_nr`, `0` inserts at the very top.
+    pub fn insert_line(&mut self, line_nr: usize, line_content: String) {
+        self.inserted
+            .entry(line_nr.min(self.lines.len()))
+            .or_default()
+            .push(line_content);
+    }
+
+    /// Adds `line_content` to the end of the file.
+    pub fn append_line(&mut self, line_content: String) {
+        self.insert_line(self.lines.len(), line_content);
+    }
+
+    /// The content of the file with all edits applied.
+    pub fn render(&self) -> String {
+        let mut result: Vec<&str> = Vec::new();
+        for line_nr in 0..=self.lines.len() {
+            if line_nr > 0 && !self.removed.contains(&line_nr) {
+                match self.replaced.get(&line_nr) {
+                    Some(line) => result.push(line),
+                    None => result.push(&self.lines[line_nr - 1]),
+                }
+            }
+            if let Some(new_lines) = self.inserted.get(&line_nr) {
+                result.extend(new_lines.iter().map(String::as_str));
+            }
+        }
+
+        let mut content = result.join(self.line_ending);
+        if self.trailing_newline && !content.is_empty() {
+            content.push_str(self.line_ending);
+        }
+        content
+    }
+
+    /// Writes all edits back to the file in one go.
+    /// If another program changed the file since it was loaded, the edits are moved to wherever
+    /// the edited lines are now. Should that be impossible, nothing is written.
+    pub fn save(&mut self) -> Result<(), TodoError> {
+        self.sync()?;
+        write_atomic(&self.path, &self.render())?;
+        *self = TodoDocument::load(&self.path)?;
+        Ok(())
+    }
+
+    /// Checks whether another program changed the file since it was loaded and if so,
+    /// moves the pending edits to wherever the edited lines are now.
+    pub fn sync(&mut self) -> Result<(), TodoError> {
+        let mtime = fs::metadata(&self.path)
+            .and_then(|meta| meta.modified())
+            .ok();
+        if mtime != self.loaded_mtime {
+            let content = fs::read_to_string(&self.path).map_err(TodoError::io(&self.path))?;
+            if content_hash(&content) != self.loaded_hash {
+                self.rebase(&content)?;
+            }
+            self.loaded_hash = content_hash(&content);
+            self.loaded_mtime = mtime;
+        }
+        Ok(())
+    }
+
+    /// Moves all pending edits onto `content` by looking up the edited lines there.
+    fn rebase(&mut self, content: &str) -> Result<(), TodoError> {
+        let fresh: Vec<String> = content.lines().map(String::from).collect();
+        let mut mapping: BTreeMap<usize, usize> = BTreeMap::new(); // old line number -> new one
+
+        // the top and the end of the file don't need to be looked up as insertion points
+        let anchors = self
+            .inserted
+            .keys()
+            .filter(|line_nr| (1..self.lines.len()).contains(*line_nr));
+        let touched: BTreeSet<usize> = self
+            .replaced
+            .keys()
+            .chain(self.removed.iter())
+            .chain(anchors)
+            .copied()
+            .collect();
+        for line_nr in touched {
+            let original = &self.lines[line_nr - 1];
+            let new_nr = fresh
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| *line == original)
+                .map(|(i, _)| i + 1)
+                .filter(|candidate| !mapping.values().any(|taken| taken == candidate))
+                .min_by_key(|candidate| candidate.abs_diff(line_nr))
+                .ok_or_else(|| TodoError::Conflict {
+                    path: self.path.clone(),
+                    line_nr,
+                    line: original.clone(),
+                })?;
+            mapping.insert(line_nr, new_nr);
+        }
+
+        self.replaced = std::mem::take(&mut self.replaced)
+            .into_iter()
+            .map(|(line_nr, line)| (mapping[&line_nr], line))
+            .collect();
+        self.removed = self
+            .removed
+            .iter()
+            .map(|line_nr| mapping[line_nr])
+            .collect();
+        self.inserted = std::mem::take(&mut self.inserted)
+            .into_iter()
+            .map(|(line_nr, lines)| {
+                let new_nr = match line_nr {
+                    0 => 0,
+                    _ if line_nr == self.lines.len() => fresh.len(),
+                    _ => mapping[&line_nr],
+                };
+                (new_nr, lines)
+            })
+            .collect();
+        self.lines = fresh;
+        self.line_ending = if content.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        self.trailing_newline = content.is_empty() || content.ends_with('\n');
+        Ok(())
+    }
+}
+
+pub(crate) fn content_hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Writes `content` into a temporary file next to `filepath` and renames it over the original,
+/// so a crash can never leave a half-written file behind.
+pub(crate) fn write_atomic(filepath: &Path, content: &str) -> Result<(), TodoError> {
+    // write through symlinks instead of replacing them
+    let target = fs::canonicalize(filepath).unwrap_or_else(|_| filepath.to_path_buf());
+    let file_name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp_path = target.with_file_name(format!(".{file_name}.{}.tmp", std::process::id()));
+
+    let result = (|| -> io::Result<()> {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(content.as_bytes())?;
+        if let Ok(metadata) = fs::metadata(&target) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()?;
+        fs::rename(&temp_path, &target)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result.map_err(TodoError::io(target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigFile;
+
+    fn document(content: &str) -> TodoDocument {
+        TodoDocument::from_content(Path::new("todo.md"), content, None)
+    }
+
+    fn logseq() -> TodoConfig {
+        let mut conf_file = ConfigFile::default();
+        conf_file.format.checkbox_style = "logseq".to_string();
+        TodoConfig::new(&conf_file)
+    }
+
+    const LOGSEQ: &str = "- TODO a\n- TODO b\n  DEADLINE: <2024-07-01 Mon .+1w>\n  SCHEDULED: <2024-06-20 Thu>\n- TODO c\n";
+
+    #[test]
+    fn removing_a_task_removes_its_logseq_dates() {
+        let conf_todo = logseq();
+        let mut doc = document(LOGSEQ);
+        let tasks = doc.tasks(&conf_todo);
+        doc.remove_task(&tasks[1], &conf_todo);
+        assert_eq!(doc.render(), "- TODO a\n- TODO c\n");
+    }
+
+    #[test]
+    fn removing_a_logseq_date_removes_its_line() {
+        let conf_todo = logseq();
+        let mut doc = document(LOGSEQ);
+        let mut task = doc.tasks(&conf_todo).remove(1);
+        task.set_date(DateKind::Due, None, &conf_todo).unwrap();
+        doc.update_task(&task, &conf_todo);
+        assert_eq!(
+            doc.render(),
+            "- TODO a\n- TODO b\n  SCHEDULED: <2024-06-20 Thu>\n- TODO c\n"
+        );
+    }
+
+    #[test]
+    fn changing_a_logseq_date_rewrites_its_line() {
+        let conf_todo = logseq();
+        let mut doc = document(LOGSEQ);
+        let mut task = doc.tasks(&conf_todo).remove(1);
+        let due = Due::Date("2024-09-09".parse().unwrap());
+        task.set_date(DateKind::Due, Some(due), &conf_todo).unwrap();
+        assert_eq!(task.raw, "- TODO b");
+        doc.update_task(&task, &conf_todo);
+        assert_eq!(
+            doc.render(),
+            "- TODO a\n- TODO b\n  DEADLINE: <2024-09-09 Mon .+1w>\n  SCHEDULED: <2024-06-20 Thu>\n- TODO c\n"
+        );
+    }
+
+    #[test]
+    fn render_keeps_line_endings() {
+        let mut doc = document("- [ ] a\r\n- [ ] b");
+        doc.set_line(2, "- [X] b".to_string());
+        doc.append_line("- [ ] c".to_string());
+        assert_eq!(doc.render(), "- [ ] a\r\n- [X] b\r\n- [ ] c");
+    }
+
+    #[test]
+    fn edits_follow_moved_lines() {
+        let mut doc = document("- [ ] a\n- [ ] b\n- [ ] c\n");
+        doc.set_line(2, "- [X] b".to_string());
+        doc.remove_line(3);
+        doc.insert_line(1, "- [ ] after a".to_string());
+
+        doc.rebase("# added meanwhile\n- [ ] a\n- [ ] b\n- [ ] c\n- [ ] d\n")
+            .unwrap();
+        assert_eq!(
+            doc.render(),
+            "# added meanwhile\n- [ ] a\n- [ ] after a\n- [X] b\n- [ ] d\n"
+        );
+    }
+
+    #[test]
+    fn duplicates_go_to_the_closest_line() {
+        let mut doc = document("- [ ] x\n- [ ] y\n- [ ] x\n");
+        doc.set_line(3, "- [X] x".to_string());
+
+        doc.rebase("- [ ] x\n- [ ] y\n- [ ] new\n- [ ] x\n")
+            .unwrap();
+        assert_eq!(doc.render(), "- [ ] x\n- [ ] y\n- [ ] new\n- [X] x\n");
+    }
+
+    #[test]
+    fn edited_line_gone_is_a_conflict() {
+        let mut doc = document("- [ ] a\n- [ ] b\n");
+        doc.set_line(2, "- [X] b".to_string());
+
+        let result = doc.rebase("- [ ] a\n- [ ] b changed elsewhere\n");
+        assert!(matches!(
+            result,
+            Err(TodoError::Conflict { line_nr: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn appended_lines_stay_at_the_end() {
+        let mut doc = document("- [ ] a\n");
+        doc.append_line("- [ ] b".to_string());
+
+        doc.rebase("- [ ] a\n- [ ] from elsewhere\n").unwrap();
+        assert_eq!(doc.render(), "- [ ] a\n- [ ] from elsewhere\n- [ ] b\n");
+    }
+
+    #[test]
+    fn added_tasks_go_before_subsections() {
+        let conf_todo = TodoConfig::new(&ConfigFile::default());
+        let mut doc = document("## Work\n- [ ] a\n### Backend\n- [ ] b\n");
+        let mut item = parse_line("- [ ] c", &conf_todo).unwrap();
+        doc.add_task(&mut item, Some("work"), &conf_todo).unwrap();
+        assert_eq!(
+            doc.render(),
+            "## Work\n- [ ] a\n- [ ] c\n### Backend\n- [ ] b\n"
+        );
+        assert_eq!((item.id, item.line), (2, 3));
+        assert_eq!(item.section, vec!["Work"]);
+    }
+
+    #[test]
+    fn toggling_only_touches_the_checkbox() {
+        let conf_todo = TodoConfig::new(&ConfigFile::default());
+        let content = "# Home\r\n\t  -   [x]   call   mom  2024-07-05 #fam ⏫  \r\n- [ ] other";
+        let mut doc = document(content);
+        let mut task = doc.tasks(&conf_todo).remove(0);
+
+        doc.set_completed(&mut task, false, &conf_todo);
+        assert_eq!(
+            doc.render(),
+            "# Home\r\n\t  -   [ ]   call   mom  2024-07-05 #fam ⏫  \r\n- [ ] other"
+        );
+        doc.set_completed(&mut task, true, &conf_todo);
+        assert_eq!(
+            doc.render(),
+            "# Home\r\n\t  -   [X]   call   mom  2024-07-05 #fam ⏫  \r\n- [ ] other"
+        );
+        assert_eq!(task.stable_id, None);
+    }
+}
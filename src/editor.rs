@@ -0,0 +1,79 @@
+//! Starting the user's editor.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use todo_md::TodoError;
+
+/// Editors that open a file at a line with `+<line> <file>`.
+const PLUS_LINE: [&str; 10] = [
+    "vi",
+    "vim",
+    "nvim",
+    "gvim",
+    "nano",
+    "emacs",
+    "emacsclient",
+    "kak",
+    "micro",
+    "joe",
+];
+/// Editors that open a file at a line with `<file>:<line>`.
+const COLON_LINE: [&str; 5] = ["hx", "helix", "zed", "subl", "sublime_text"];
+/// Editors that open a file at a line with `-g <file>:<line>`.
+const GOTO_LINE: [&str; 5] = ["code", "code-insiders", "codium", "vscodium", "cursor"];
+
+/// `$VISUAL`, `$EDITOR` or `vi`, split into the program and its arguments.
+fn editor_command() -> Vec<String> {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .map(|editor| {
+            editor
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        })
+        .find(|command| !command.is_empty())
+        .unwrap_or_else(|| vec!["vi".to_string()])
+}
+
+/// Opens `file` at `line` and waits until the editor is closed.
+/// GUI editors are told to wait as well, so the file can be read afterwards.
+pub fn open_at(file: &Path, line: usize) -> Result<(), TodoError> {
+    let mut command = editor_command();
+    let program = command.remove(0);
+    let name = Path::new(&program)
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let file = file.display();
+
+    let mut args = command;
+    if PLUS_LINE.contains(&name.as_str()) {
+        args.extend([format!("+{line}"), file.to_string()]);
+    } else if COLON_LINE.contains(&name.as_str()) {
+        if matches!(name.as_str(), "zed" | "subl" | "sublime_text")
+            && !args.contains(&"--wait".to_string())
+        {
+            args.push("--wait".to_string());
+        }
+        args.push(format!("{file}:{line}"));
+    } else if GOTO_LINE.contains(&name.as_str()) {
+        if !args.iter().any(|arg| arg == "--wait" || arg == "-w") {
+            args.push("--wait".to_string());
+        }
+        args.extend(["-g".to_string(), format!("{file}:{line}")]);
+    } else {
+        args.push(file.to_string());
+    }
+
+    let status = Command::new(&program)
+        .args(&args)
+        .status()
+        .map_err(TodoError::io(PathBuf::from(&program)))?;
+    if !status.success() {
+        // e.g. `:cq` in vim
+        return Err(TodoError::Aborted);
+    }
+    Ok(())
+}
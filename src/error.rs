@@ -0,0 +1,115 @@
+//! Everything that can go wrong.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Errors of all operations, each with enough context to tell the user what happened.
+#[derive(Debug)]
+pub enum TodoError {
+    /// reading or writing a file failed
+    Io {
+        /// the file or directory
+        path: PathBuf,
+        /// what the OS reported
+        source: io::Error,
+    },
+    /// reading from the terminal failed
+    Input(io::Error),
+    /// the config file can't be used
+    Config {
+        /// the config file
+        path: PathBuf,
+        /// what's wrong with it
+        message: String,
+    },
+    /// another program changed the file and the edited line is gone
+    Conflict {
+        /// the todo file
+        path: PathBuf,
+        /// where the line was when the file was loaded, 1-indexed
+        line_nr: usize,
+        /// the line as it was when the file was loaded
+        line: String,
+    },
+    /// another process holds the lock of the file
+    Locked {
+        /// the todo file or vault
+        path: PathBuf,
+        /// the file the lock is taken on
+        lock_path: PathBuf,
+    },
+    /// no task has this positional or stable ID
+    TaskNotFound(String),
+    /// no heading has this name
+    SectionNotFound(String),
+    /// the input can't be turned into a task
+    InvalidTask(String),
+    /// the user declined to continue
+    Aborted,
+    /// a question would have to be asked, but prompting isn't possible
+    InputRequired(String),
+    /// a filter expression can't be parsed
+    InvalidQuery(String),
+    /// no view has this name
+    ViewNotFound(String),
+    /// the input can't be turned into a date
+    InvalidDate(String),
+    /// the input can't be turned into a priority
+    InvalidPriority(String),
+}
+
+impl TodoError {
+    /// Shorthand for `map_err` on I/O results.
+    pub fn io(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> TodoError {
+        let path = path.into();
+        move |source| TodoError::Io { path, source }
+    }
+}
+
+impl fmt::Display for TodoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TodoError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            TodoError::Input(source) => write!(f, "unable to read your input: {source}"),
+            TodoError::Config { path, message } => {
+                write!(f, "error in your config {}: {message}", path.display())
+            }
+            TodoError::Conflict {
+                path,
+                line_nr,
+                line,
+            } => write!(
+                f,
+                "{} was changed by another program and line {line_nr} (\"{}\") can't be found anymore, nothing was written",
+                path.display(),
+                line.trim()
+            ),
+            TodoError::Locked { path, .. } => {
+                write!(f, "{} is being edited by another todo process", path.display())
+            }
+            TodoError::TaskNotFound(selector) => write!(f, "there is no task with ID {selector}"),
+            TodoError::SectionNotFound(name) => write!(f, "there is no section called {name}"),
+            TodoError::InvalidTask(input) => {
+                write!(f, "unable to convert \"{input}\" into a task, sorry")
+            }
+            TodoError::Aborted => write!(f, "aborted"),
+            TodoError::InputRequired(question) => {
+                write!(f, "unable to ask \"{}\" without a terminal", question.trim())
+            }
+            TodoError::InvalidQuery(message) => write!(f, "invalid query: {message}"),
+            TodoError::ViewNotFound(name) => write!(f, "there is no view called {name}"),
+            TodoError::InvalidDate(input) => write!(f, "\"{input}\" is not a valid date"),
+            TodoError::InvalidPriority(input) => write!(f, "\"{input}\" is not a valid priority"),
+        }
+    }
+}
+
+impl std::error::Error for TodoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TodoError::Io { source, .. } | TodoError::Input(source) => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! Remembering parsed tasks between runs.
+
+use crate::config::TodoConfig;
+use crate::document::{content_hash, write_atomic};
+use crate::error::TodoError;
+use crate::item::*;
+use crate::workspace::{concat_tasks, is_skippable};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Bumped whenever `TodoItem` or the way it's parsed changes, which throws away older indexes.
+const INDEX_VERSION: u32 = 5;
+
+/// Files changed this recently might be changed again within the same mtime tick,
+/// so their mtime isn't trusted.
+const RACY_MTIME: Duration = Duration::from_secs(2);
+
+/// Parsed tasks of every file `todo` has read, so only files that changed since have to be parsed again.
+/// It's only a cache: a missing or broken index is rebuilt without further notice.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TaskIndex {
+    version: u32,
+    checkbox_style: String, // tasks parsed with another style are useless
+    files: BTreeMap<PathBuf, IndexEntry>,
+    #[serde(skip)]
+    modified: bool,
+    #[serde(skip)]
+    skipped: Vec<PathBuf>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct IndexEntry {
+    mtime: Option<SystemTime>, // `None` if it's too recent to be trusted
+    hash: u64,
+    tasks: Vec<TodoItem>,
+}
+
+impl TaskIndex {
+    /// An empty index for files parsed with `checkbox_style`.
+    pub fn new(checkbox_style: &str) -> TaskIndex {
+        TaskIndex {
+            version: INDEX_VERSION,
+            checkbox_style: checkbox_style.to_string(),
+            files: BTreeMap::new(),
+            modified: true,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Where the index is kept, usually `~/.cache/todo-md-rs/index.json`.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("todo-md-rs").join("index.json"))
+    }
+
+    /// Reads the index at `path`, or starts a new one if it doesn't exist, can't be read
+    /// or was made for another `checkbox_style` or version.
+    pub fn load(path: &Path, checkbox_style: &str) -> TaskIndex {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<TaskIndex>(&content).ok())
+            .filter(|index| {
+                index.version == INDEX_VERSION && index.checkbox_style == checkbox_style
+            })
+            .unwrap_or_else(|| TaskIndex::new(checkbox_style))
+    }
+
+    /// All tasks of `files`, like `Workspace::tasks`.
+    /// Files are only read if their mtime changed and only parsed if their content did.
+    /// Notes in `vault` that aren't valid UTF-8 are left out like in `Workspace::load`, see `skipped`.
+    pub fn tasks(
+        &mut self,
+        files: &[PathBuf],
+        vault: Option<&Path>,
+        conf_todo: &TodoConfig,
+    ) -> Result<Vec<TodoItem>, TodoError> {
+        self.skipped.clear();
+        for file in files {
+            let mtime = fs::metadata(file).and_then(|meta| meta.modified()).ok();
+            if mtime.is_some()
+                && self
+                    .files
+                    .get(file)
+                    .is_some_and(|entry| entry.mtime == mtime)
+            {
+                continue;
+            }
+
+            let content = match fs::read_to_string(file).map_err(TodoError::io(file)) {
+                Ok(content) => content,
+                Err(e) if is_skippable(file, vault, &e) => {
+                    self.files.remove(file);
+                    self.skipped.push(file.clone());
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let hash = content_hash(&content);
+            let trusted_mtime =
+                mtime.filter(|mtime| mtime.elapsed().is_ok_and(|elapsed| elapsed >= RACY_MTIME));
+            match self.files.get_mut(file) {
+                Some(entry) if entry.hash == hash => entry.mtime = trusted_mtime,
+                _ => {
+                    let lines: Vec<String> = content.lines().map(String::from).collect();
+                    let entry = IndexEntry {
+                        mtime: trusted_mtime,
+                        hash,
+                        tasks: strings_to_todo(lines, file, conf_todo),
+                    };
+                    self.files.insert(file.clone(), entry);
+                }
+            }
+            self.modified = true;
+        }
+
+        // forget files that are gone
+        let before = self.files.len();
+        self.files.retain(|path, _| path.exists());
+        self.modified |= self.files.len() != before;
+
+        Ok(concat_tasks(files.iter().map(|file| {
+            self.files
+                .get(file)
+                .map(|entry| entry.tasks.clone())
+                .unwrap_or_default()
+        })))
+    }
+
+    /// Notes left out by the last call of `tasks`.
+    pub fn skipped(&self) -> &[PathBuf] {
+        &self.skipped
+    }
+
+    /// Writes the index to `path` if anything changed.
+    pub fn save(&mut self, path: &Path) -> Result<(), TodoError> {
+        if !self.modified {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(TodoError::io(parent))?;
+        }
+        let content = serde_json::to_string(self).map_err(|e| TodoError::io(path)(e.into()))?;
+        write_atomic(path, &content)?;
+        self.modified = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigFile;
+    use crate::testing::TempDir;
+    use std::fs::File;
+
+    /// Writes `content` to `path` and sets its mtime to `age` ago.
+    fn write(path: &Path, content: &str, age: Duration) {
+        fs::write(path, content).unwrap();
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    fn titles(tasks: &[TodoItem]) -> Vec<&str> {
+        tasks.iter().map(|item| item.title.as_str()).collect()
+    }
+
+    const OLD: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn unchanged_files_are_not_read_again() {
+        let dir = TempDir::new("index-mtime");
+        let conf_todo = TodoConfig::new(&ConfigFile::default());
+        let files = [dir.path().join("todo.md")];
+        let file = &files[0];
+        write(file, "- [ ] a\n", OLD);
+        let mut index = TaskIndex::new("md");
+        assert_eq!(
+            titles(&index.tasks(&files, None, &conf_todo).unwrap()),
+            ["a"]
+        );
+
+        // a new mtime with the same content is taken over without parsing again
+        index.files.get_mut(file).unwrap().tasks[0].title = "cached".to_string();
+        write(file, "- [ ] a\n", OLD * 2);
+        assert_eq!(
+            titles(&index.tasks(&files, None, &conf_todo).unwrap()),
+            ["cached"]
+        );
+        let mtime = fs::metadata(file).unwrap().modified().unwrap();
+        assert_eq!(index.files[file].mtime, Some(mtime));
+
+        // same mtime, so the content isn't even looked at
+        fs::write(file, "- [ ] b\n").unwrap();
+        File::options()
+            .write(true)
+            .open(file)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        assert_eq!(
+            titles(&index.tasks(&files, None, &conf_todo).unwrap()),
+            ["cached"]
+        );
+
+        // until it changes
+        write(file, "- [ ] b\n", OLD);
+        assert_eq!(
+            titles(&index.tasks(&files, None, &conf_todo).unwrap()),
+            ["b"]
+        );
+    }
+
+    #[test]
+    fn racy_mtimes_are_not_trusted() {
+        let dir = TempDir::new("index-racy");
+        let conf_todo = TodoConfig::new(&ConfigFile::default());
+        let files = [dir.path().join("todo.md")];
+        let file = &files[0];
+        write(file, "- [ ] a\n", Duration::ZERO);
+        let mut index = TaskIndex::new("md");
+        index.tasks(&files, None, &conf_todo).unwrap();
+        assert_eq!(index.files[file].mtime, None);
+
+        // changed within the same mtime tick
+        let mtime = fs::metadata(file).unwrap().modified().unwrap();
+        fs::write(file, "- [ ] b\n").unwrap();
+        File::options()
+            .write(true)
+            .open(file)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+        assert_eq!(
+            titles(&index.tasks(&files, None, &conf_todo).unwrap()),
+            ["b"]
+        );
+    }
+
+    #[test]
+    fn deleted_files_are_dropped() {
+        let dir = TempDir::new("index-deleted");
+        let conf_todo = TodoConfig::new(&ConfigFile::default());
+        let files = [dir.path().join("a.md"), dir.path().join("b.md")];
+        write(&files[0], "- [ ] a\n", OLD);
+        write(&files[1], "- [ ] b\n", OLD);
+        let mut index = TaskIndex::new("md");
+        let tasks = index.tasks(&files, None, &conf_todo).unwrap();
+        assert_eq!(titles(&tasks), ["a", "b"]);
+        assert_eq!(tasks[1].id, 2);
+
+        fs::remove_file(&files[1]).unwrap();
+        index.modified = false;
+        assert_eq!(
+            titles(&index.tasks(&files[..1], None, &conf_todo).unwrap()),
+            ["a"]
+        );
+        assert!(!index.files.contains_key(&files[1]));
+        assert!(index.modified);
+    }
+}
@@ -0,0 +1,1266 @@
+//! Parsing lines into tasks.
+
+use crate::config::TodoConfig;
+use crate::dates::{DateKind, Due};
+use crate::error::TodoError;
+use crate::priority::Priority;
+use crate::recurrence::Recurrence;
+use chrono::{NaiveDate, NaiveTime};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Parses a Vec of raw Strings and writes them into the Todo container.
+/// A task will only be recognised by the configured `TodoConfig.completion_style`
+/// `file` is where the lines come from and is stored in every task.
+pub fn strings_to_todo(lines: Vec<String>, file: &Path, conf_todo: &TodoConfig) -> Vec<TodoItem> {
+    let mut item_list: Vec<TodoItem> = Vec::new();
+    let mut headings: Vec<(usize, String)> = Vec::new(); // (level, title) of the headings we're currently in
+    let mut parents: Vec<(usize, usize)> = Vec::new(); // (indentation, index in item_list) of possible parent tasks
+
+    for (linecount, line) in lines.iter().enumerate() {
+        // - heading detected -
+        if let Some((level, title)) = parse_heading(line) {
+            headings.retain(|(outer, _)| *outer < level);
+            headings.push((level, title));
+            parents.clear();
+            continue;
+        }
+        let indent = indentation(line);
+
+        // - Logseq `SCHEDULED:` or `DEADLINE:` right under a task -
+        if let Some((kind, date, _)) = parse_logseq_date(line, conf_todo) {
+            let task = item_list.last_mut().filter(|task| {
+                lines[task.line..linecount]
+                    .iter()
+                    .all(|between| conf_todo.logseq_date.is_match(between))
+            });
+            if let Some(task) = task {
+                match kind {
+                    DateKind::Due if task.date_due.is_none() => task.date_due = Some(date),
+                    DateKind::Scheduled if task.date_scheduled.is_none() => {
+                        task.date_scheduled = Some(date.date())
+                    }
+                    _ => (),
+                }
+                continue;
+            }
+        }
+
+        // - task detected by completion pattern -
+        if let Some(mut item) = parse_line(line, conf_todo) {
+            // ID and LINE, duh
+            item.id = item_list.len() + 1;
+            item.line = linecount + 1;
+            item.file = file.to_path_buf();
+            item.section = headings.iter().map(|(_, title)| title.clone()).collect();
+
+            // PARENT: the closest task above that is indented less
+            while parents.last().is_some_and(|(outer, _)| *outer >= indent) {
+                parents.pop();
+            }
+            if let Some((_, parent)) = parents.last() {
+                item.parent = Some(item_list[*parent].id);
+                item.depth = parents.len();
+                item_list[*parent].children.push(item.id);
+            }
+            parents.push((indent, item_list.len()));
+
+            item_list.push(item);
+        } else if indent == 0 && !line.trim().is_empty() {
+            // a paragraph ends any list of subtasks
+            parents.clear();
+        }
+    }
+
+    item_list
+}
+
+/// Parses a Logseq `SCHEDULED:` or `DEADLINE:` line into the kind of its date, the date and
+/// where it's written, from the date up to the end of the weekday and time.
+/// Only deadlines keep their time.
+pub(crate) fn parse_logseq_date(
+    line: &str,
+    conf_todo: &TodoConfig,
+) -> Option<(DateKind, Due, Range<usize>)> {
+    let captures = conf_todo.logseq_date.captures(line)?;
+    let date = captures[2].parse::<NaiveDate>().ok()?;
+    let time = captures
+        .get(3)
+        .and_then(|time| NaiveTime::parse_from_str(time.as_str(), "%H:%M").ok());
+    let (kind, due) = match (&captures[1], time) {
+        ("DEADLINE", Some(time)) => (DateKind::Due, Due::DateTime(date.and_time(time))),
+        ("DEADLINE", None) => (DateKind::Due, Due::Date(date)),
+        _ => (DateKind::Scheduled, Due::Date(date)),
+    };
+    let span = captures.get(2)?.start()..captures.get(0)?.end();
+    Some((kind, due, span))
+}
+
+/// How Logseq writes a date between `<` and `>`, like `2024-07-05 Fri` or `2024-07-05 Fri 14:00`.
+pub(crate) fn logseq_date_text(due: Due) -> String {
+    match due.local_time() {
+        Some(time) => time.format("%Y-%m-%d %a %H:%M").to_string(),
+        None => due.date().format("%Y-%m-%d %a").to_string(),
+    }
+}
+
+/// Parses a single line into a task, keeping the raw line and where each component was found.
+/// Position related fields (`id`, `line`, `section`, ...) are left for the caller to fill in.
+pub fn parse_line(line: &str, conf_todo: &TodoConfig) -> Option<TodoItem> {
+    let checkbox = conf_todo.completion_style.captures(line)?;
+    let body_start = checkbox.get(0).unwrap().end();
+    let mut item = TodoItem::new();
+    item.raw = line.to_string();
+
+    // COMPLETION
+    item.spans.checkbox = checkbox.get(1).unwrap().range();
+    item.is_completed = conf_todo.completion_done.is_match(line);
+
+    // STABLE ID
+    if let Some(captures) = conf_todo.stable_id.captures_at(line, body_start) {
+        let id = captures.get(1).unwrap();
+        item.stable_id = Some(id.as_str().to_string());
+        item.spans.stable_id = Some(id.start() - 1..id.end()); // including the `^`
+    }
+
+    // PRIORITY: the first marker, a todo.txt `(A)` only counts right after the checkbox
+    let mut search_from = body_start;
+    while let Some(captures) = conf_todo.priority_format.captures_at(line, search_from) {
+        let marker = captures.get(1).unwrap();
+        if !marker.as_str().starts_with('(') || line[body_start..marker.start()].trim().is_empty() {
+            item.priority = Priority::from_marker(marker.as_str());
+            item.spans.priority = Some(marker.range());
+            break;
+        }
+        search_from = marker.end();
+    }
+
+    // DATES with a marker like `⏳ 2024-07-05` or `due:2024-07-05`, the first of each kind counts
+    let mut search_from = body_start;
+    while let Some(captures) = conf_todo.date_marker.captures_at(line, search_from) {
+        let (marker, date) = (captures.get(1).unwrap(), captures.get(2).unwrap());
+        search_from = date.end();
+        let (Some(kind), Some(parsed)) = (
+            DateKind::from_marker(marker.as_str()),
+            Due::parse(date.as_str()),
+        ) else {
+            continue;
+        };
+        if item.date_span(kind).is_none() {
+            item.store_date(kind, parsed, date.range());
+            item.spans.date_markers.push(marker.range());
+        }
+    }
+
+    // todo.txt: `x COMPLETED CREATED title` and `(A) CREATED title`
+    let todotxt_priority = item
+        .spans
+        .priority
+        .clone()
+        .filter(|span| line[span.clone()].starts_with('('));
+    let dates_start = todotxt_priority
+        .as_ref()
+        .map_or(body_start, |span| span.end);
+    if let Some(captures) = conf_todo.leading_dates.captures(&line[dates_start..]) {
+        let offset = |date: regex::Match| date.start() + dates_start..date.end() + dates_start;
+        let parse = |date: regex::Match| Due::parse(date.as_str());
+        match (captures.get(1), captures.get(2)) {
+            (Some(completed), Some(created)) if item.is_completed => {
+                if let (Some(completed_date), Some(created_date)) =
+                    (parse(completed), parse(created))
+                {
+                    item.store_date(DateKind::Done, completed_date, offset(completed));
+                    item.store_date(DateKind::Created, created_date, offset(created));
+                }
+            }
+            (Some(created), _) if todotxt_priority.is_some() => {
+                if let Some(created_date) = parse(created) {
+                    item.store_date(DateKind::Created, created_date, offset(created));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // DUE: without a marker, assume the first valid date is the due date.
+    // Anything like `2024-13-45` stays in the title
+    let mut search_from = body_start;
+    while item.spans.date_due.is_none() {
+        let Some(captures) = conf_todo.date_format.captures_at(line, search_from) else {
+            break;
+        };
+        let date = captures.get(1).unwrap();
+        search_from = date.end();
+        let taken = item
+            .spans
+            .dates()
+            .any(|span| span.start < date.end() && date.start() < span.end);
+        if let Some(parsed) = Due::parse(date.as_str()).filter(|_| !taken) {
+            item.store_date(DateKind::Due, parsed, date.range());
+        }
+    }
+
+    // RECURRENCE: `🔁 every week`, `every 2nd monday` or `rec:1w`, as many words as make a valid rule
+    let words = word_spans(line, body_start);
+    for (i, word) in words.iter().enumerate() {
+        let rule_start = if &line[word.clone()] == "🔁" {
+            i + 1
+        } else {
+            i
+        };
+        let Some(first) = words.get(rule_start) else {
+            break;
+        };
+        let first_word = line[first.clone()].to_lowercase();
+        if first_word != "every" && !first_word.starts_with("rec:") {
+            continue;
+        }
+        let found = (rule_start..words.len().min(rule_start + 8))
+            .rev()
+            .find_map(|last| {
+                Recurrence::parse(&line[first.start..words[last].end])
+                    .map(|rule| (rule, words[last].end))
+            });
+        if let Some((rule, end)) = found {
+            item.recurrence = Some(rule);
+            item.spans.recurrence = Some(word.start..end);
+            break;
+        }
+    }
+
+    // TAG
+    for tag in conf_todo.tag_format.find_iter(&line[body_start..]) {
+        item.tags.push(tag.as_str().into());
+        item.spans
+            .tags
+            .push(tag.start() + body_start..tag.end() + body_start);
+    }
+
+    // NAME
+    for name in conf_todo.name_format.find_iter(&line[body_start..]) {
+        item.names.push(name.as_str().into());
+        item.spans
+            .names
+            .push(name.start() + body_start..name.end() + body_start);
+    }
+
+    // TITLE: take what's left
+    let mut parsed: Vec<&Range<usize>> = item.spans.components().collect();
+    parsed.sort_by_key(|span| span.start);
+    let mut title = String::new();
+    let mut pos = body_start;
+    for span in parsed {
+        if span.start >= pos {
+            title.push_str(&line[pos..span.start]);
+            title.push(' ');
+            pos = span.end;
+        }
+    }
+    title.push_str(&line[pos..]);
+    item.title = title.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+    Some(item)
+}
+
+/// Byte ranges of the whitespace separated words of `line`, starting at `from`.
+fn word_spans(line: &str, from: usize) -> Vec<Range<usize>> {
+    let mut words: Vec<Range<usize>> = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, c) in line[from..].char_indices() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(from + i),
+            (true, Some(word_start)) => {
+                words.push(word_start..from + i);
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(word_start) = start {
+        words.push(word_start..line.len());
+    }
+    words
+}
+
+/// Width of the leading whitespace of `line`, counting tabs as four spaces.
+pub(crate) fn indentation(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+/// Returns level and title if `line` is a Markdown heading like `## Work`.
+pub(crate) fn parse_heading(line: &str) -> Option<(usize, String)> {
+    let hashes = line.len() - line.trim_start_matches('#').len();
+    let rest = &line[hashes..];
+    if (1..=6).contains(&hashes) && rest.starts_with(char::is_whitespace) {
+        let title = rest.trim().trim_end_matches('#').trim_end();
+        Some((hashes, title.to_string()))
+    } else {
+        None
+    }
+}
+
+/// Finds the line number after which a new task of section `name` should be inserted:
+/// the last non-empty line before the next heading. Subsections are left out, so the task
+/// ends up directly under `name` instead of in the last of them.
+pub(crate) fn find_section_end(lines: &[String], name: &str) -> Option<usize> {
+    let start = lines.iter().position(|line| {
+        parse_heading(line).is_some_and(|(_, title)| title.eq_ignore_ascii_case(name))
+    })?;
+
+    let mut end = start;
+    for (i, line) in lines.iter().enumerate().skip(start + 1) {
+        match parse_heading(line) {
+            Some(_) => break,
+            _ if !line.trim().is_empty() => end = i,
+            _ => (),
+        }
+    }
+
+    Some(end + 1)
+}
+
+/// Generates a short identifier that is not contained in `taken`.
+/// It always starts with a letter so it can't be mistaken for a positional ID.
+pub(crate) fn new_stable_id(taken: &[&str]) -> String {
+    const ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos())
+        .unwrap_or_default();
+
+    loop {
+        let mut id = String::new();
+        let mut rest = seed;
+        id.push(ALPHABET[10 + (rest % 26) as usize] as char);
+        rest /= 26;
+        for _ in 0..5 {
+            id.push(ALPHABET[(rest % 36) as usize] as char);
+            rest /= 36;
+        }
+        if !taken.contains(&id.as_str()) {
+            return id;
+        }
+        seed += 1;
+    }
+}
+
+/// Byte ranges of the parsed components within `TodoItem.raw`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TodoSpans {
+    /// Only the marker, e.g. `[ ]` or `TODO`.
+    pub checkbox: Range<usize>,
+    /// The stable ID including its `^`.
+    pub stable_id: Option<Range<usize>>,
+    /// Only the date, without its marker, like the other dates.
+    pub date_due: Option<Range<usize>>,
+    /// Only the date.
+    pub date_scheduled: Option<Range<usize>>,
+    /// Only the date.
+    pub date_start: Option<Range<usize>>,
+    /// Only the date.
+    pub date_created: Option<Range<usize>>,
+    /// Only the date.
+    pub date_done: Option<Range<usize>>,
+    /// Markers like `📅` or `due:` in front of one of the dates.
+    pub date_markers: Vec<Range<usize>>,
+    /// The priority marker, like `⏫` or `(A)`.
+    pub priority: Option<Range<usize>>,
+    /// The rule including a leading `🔁`.
+    pub recurrence: Option<Range<usize>>,
+    /// Each tag including its `#`.
+    pub tags: Vec<Range<usize>>,
+    /// Each name including its `@`.
+    pub names: Vec<Range<usize>>,
+}
+impl TodoSpans {
+    /// All spans that aren't part of the title.
+    fn components(&self) -> impl Iterator<Item = &Range<usize>> {
+        self.stable_id
+            .iter()
+            .chain(self.dates())
+            .chain(self.date_markers.iter())
+            .chain(self.priority.iter())
+            .chain(self.recurrence.iter())
+            .chain(self.tags.iter())
+            .chain(self.names.iter())
+    }
+
+    /// The spans of all dates, without their markers.
+    fn dates(&self) -> impl Iterator<Item = &Range<usize>> {
+        self.date_due
+            .iter()
+            .chain(self.date_scheduled.iter())
+            .chain(self.date_start.iter())
+            .chain(self.date_created.iter())
+            .chain(self.date_done.iter())
+    }
+
+    /// `date` together with the marker in front of it, if it has one.
+    fn with_marker(&self, date: &Range<usize>) -> Range<usize> {
+        self.date_markers
+            .iter()
+            .find(|marker| marker.end == date.start || marker.end + 1 == date.start)
+            .map_or(date.clone(), |marker| marker.start..date.end)
+    }
+
+    /// Updates all spans after `replaced` was overwritten with `new_len` bytes.
+    fn replace(&mut self, replaced: &Range<usize>, new_len: usize) {
+        let all = std::iter::once(&mut self.checkbox)
+            .chain(self.stable_id.iter_mut())
+            .chain(self.date_due.iter_mut())
+            .chain(self.date_scheduled.iter_mut())
+            .chain(self.date_start.iter_mut())
+            .chain(self.date_created.iter_mut())
+            .chain(self.date_done.iter_mut())
+            .chain(self.date_markers.iter_mut())
+            .chain(self.priority.iter_mut())
+            .chain(self.recurrence.iter_mut())
+            .chain(self.tags.iter_mut())
+            .chain(self.names.iter_mut());
+        for span in all {
+            if span == replaced {
+                span.end = span.start + new_len;
+            } else if span.start >= replaced.end {
+                span.start = span.start + new_len - replaced.len();
+                span.end = span.end + new_len - replaced.len();
+            }
+        }
+    }
+}
+
+/// Changes to an existing task, applied by `TodoItem::apply`.
+#[derive(Debug, Clone, Default)]
+pub struct TaskEdit {
+    /// New title, dates, tags and names, parsed like input to `add`.
+    pub content: Option<String>,
+    /// New due date, `Some(None)` removes it.
+    pub due: Option<Option<Due>>,
+    /// New priority, `Some(None)` removes it.
+    pub priority: Option<Option<Priority>>,
+    /// Tags to add, with or without `#`.
+    pub add_tags: Vec<String>,
+    /// Tags to remove, with or without `#`.
+    pub remove_tags: Vec<String>,
+    /// Names to add, with or without `@`.
+    pub assign: Vec<String>,
+    /// Names to remove, with or without `@`.
+    pub unassign: Vec<String>,
+}
+impl TaskEdit {
+    /// Checks whether the edit wouldn't change anything.
+    pub fn is_empty(&self) -> bool {
+        self.content.is_none()
+            && self.due.is_none()
+            && self.priority.is_none()
+            && self.add_tags.is_empty()
+            && self.remove_tags.is_empty()
+            && self.assign.is_empty()
+            && self.unassign.is_empty()
+    }
+}
+
+/// A single task: where it is, its parsed properties and the line it was parsed from.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TodoItem {
+    /// Positional ID, counting the tasks from 1 in the order of the files.
+    pub id: usize,
+    /// The file the task is in.
+    pub file: PathBuf,
+    /// 1-indexed line number within `file`.
+    // should probably be a tuple of start/end (see logseq task block would remain junk)
+    pub line: usize,
+    /// Persisted in the line as a trailing `^id` marker.
+    pub stable_id: Option<String>,
+    /// Titles of the headings the task is nested in, outermost first.
+    pub section: Vec<String>,
+    /// ID of the task this one is indented under.
+    pub parent: Option<usize>,
+    /// IDs of the tasks directly indented under this one.
+    pub children: Vec<usize>,
+    /// How many tasks this one is nested in.
+    pub depth: usize,
+    /// Whether the checkbox is checked.
+    pub is_completed: bool,
+    /// What's left of the line without checkbox, dates, tags and the like.
+    pub title: String,
+    /// The due date, with a time if it has one.
+    pub date_due: Option<Due>,
+    /// When the task is planned to be worked on.
+    pub date_scheduled: Option<NaiveDate>,
+    /// When the task can be started at the earliest.
+    pub date_start: Option<NaiveDate>,
+    /// When the task was created.
+    pub date_created: Option<NaiveDate>,
+    /// When the task was completed.
+    pub date_done: Option<NaiveDate>,
+    /// How important the task is.
+    pub priority: Option<Priority>,
+    /// How the task recurs once it's done.
+    pub recurrence: Option<Recurrence>,
+    /// Tags including their `#`.
+    pub tags: Vec<String>,
+    /// Assigned names including their `@`.
+    pub names: Vec<String>,
+    /// The line as it is in the file.
+    pub raw: String,
+    /// Where the parsed properties are in `raw`.
+    pub spans: TodoSpans,
+}
+impl Default for TodoItem {
+    fn default() -> TodoItem {
+        TodoItem::new()
+    }
+}
+impl TodoItem {
+    /// An empty task that isn't in any file.
+    pub fn new() -> TodoItem {
+        TodoItem {
+            id: 0,
+            file: PathBuf::new(),
+            line: 0,
+            stable_id: None,
+            section: Vec::new(),
+            parent: None,
+            children: Vec::new(),
+            depth: 0,
+            is_completed: false,
+            title: String::from(""),
+            date_due: None,
+            date_scheduled: None,
+            date_start: None,
+            date_created: None,
+            date_done: None,
+            priority: None,
+            recurrence: None,
+            tags: Vec::new(),
+            names: Vec::new(),
+            raw: String::new(),
+            spans: TodoSpans::default(),
+        }
+    }
+
+    /// Turns user input like `call mom 2024-07-05 #family` into a new, open task.
+    /// It gets a stable ID that none of the `existing` tasks uses yet.
+    pub fn from_input(
+        input_content: &str,
+        conf_todo: &TodoConfig,
+        existing: &[TodoItem],
+    ) -> Result<TodoItem, TodoError> {
+        let invalid = || TodoError::InvalidTask(input_content.to_string());
+        let mut item = parse_line(
+            &format!("{} {input_content}", conf_todo.example_todo),
+            conf_todo,
+        )
+        .ok_or_else(invalid)?;
+        let taken: Vec<&str> = existing
+            .iter()
+            .filter_map(|item| item.stable_id.as_deref())
+            .collect();
+        item.stable_id = Some(new_stable_id(&taken));
+
+        // parse the normalized line again so that `raw` and `spans` match
+        let mut item =
+            parse_line(&TodoItem::get_string(&item, conf_todo), conf_todo).ok_or_else(invalid)?;
+        item.id = existing.len() + 1;
+        Ok(item)
+    }
+
+    /// Moves the positional ID and the ones referring to other tasks by `offset`,
+    /// for when tasks of several files are listed together.
+    pub fn offset_ids(&mut self, offset: usize) {
+        self.id += offset;
+        if let Some(parent) = &mut self.parent {
+            *parent += offset;
+        }
+        for child in &mut self.children {
+            *child += offset;
+        }
+    }
+
+    /// Replaces `span` of the raw line with `content`, keeping the other spans in place.
+    pub fn replace_span(&mut self, span: Range<usize>, content: &str) {
+        self.raw.replace_range(span.clone(), content);
+        self.spans.replace(&span, content.len());
+    }
+
+    /// Toggles the checkbox of the raw line, leaving everything else untouched.
+    pub fn set_completed(&mut self, completed: bool, conf_todo: &TodoConfig) {
+        self.is_completed = completed;
+        let marker = if completed {
+            &conf_todo.checkbox_done
+        } else {
+            &conf_todo.checkbox_todo
+        };
+        self.replace_span(self.spans.checkbox.clone(), marker);
+    }
+
+    /// Changes the raw line as described by `edit` and parses it again.
+    /// Indentation, checkbox and stable ID stay as they are, and so does everything `edit` doesn't touch.
+    pub fn apply(&mut self, edit: &TaskEdit, conf_todo: &TodoConfig) -> Result<(), TodoError> {
+        if let Some(content) = &edit.content {
+            let invalid = || TodoError::InvalidTask(content.clone());
+            let prefix_end = conf_todo
+                .completion_style
+                .find(&self.raw)
+                .map(|checkbox| checkbox.end())
+                .ok_or_else(invalid)?;
+            if content.trim().is_empty() {
+                return Err(invalid());
+            }
+            let mut line = format!("{} {}", &self.raw[..prefix_end], content.trim());
+            if let Some(stable_id) = &self.stable_id {
+                line.push_str(&format!(" ^{stable_id}"));
+            }
+            self.raw = line;
+            self.reparse(conf_todo)?;
+        }
+
+        if let Some(due) = edit.due {
+            self.set_date(DateKind::Due, due, conf_todo)?;
+        }
+
+        if let Some(priority) = edit.priority {
+            match (self.spans.priority.clone(), priority) {
+                (Some(span), Some(priority)) => self.replace_span(span, priority.marker()),
+                (Some(span), None) => self.remove_span(span),
+                (None, Some(priority)) => self.insert_component(priority.marker()),
+                (None, None) => (),
+            }
+            self.reparse(conf_todo)?;
+        }
+
+        for tag in &edit.add_tags {
+            let tag = format!("#{}", tag.trim_start_matches('#'));
+            self.add_marker(&tag, &conf_todo.tag_format, conf_todo)?;
+        }
+        for tag in &edit.remove_tags {
+            self.remove_marker(&format!("#{}", tag.trim_start_matches('#')), conf_todo)?;
+        }
+        for name in &edit.assign {
+            let name = format!("@{}", name.trim_start_matches('@'));
+            self.add_marker(&name, &conf_todo.name_format, conf_todo)?;
+        }
+        for name in &edit.unassign {
+            self.remove_marker(&format!("@{}", name.trim_start_matches('@')), conf_todo)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the whole raw line, e.g. after it was edited by hand.
+    pub fn replace_line(&mut self, line: &str, conf_todo: &TodoConfig) -> Result<(), TodoError> {
+        let previous = std::mem::replace(&mut self.raw, line.to_string());
+        self.reparse(conf_todo).inspect_err(|_| self.raw = previous)
+    }
+
+    /// Adds a tag or name unless the task already has it. `format` is the pattern it has to match.
+    fn add_marker(
+        &mut self,
+        marker: &str,
+        format: &Regex,
+        conf_todo: &TodoConfig,
+    ) -> Result<(), TodoError> {
+        if !format
+            .find(marker)
+            .is_some_and(|found| found.range() == (0..marker.len()))
+        {
+            return Err(TodoError::InvalidTask(marker.to_string()));
+        }
+        if !self
+            .tags
+            .iter()
+            .chain(&self.names)
+            .any(|existing| existing.eq_ignore_ascii_case(marker))
+        {
+            self.insert_component(marker);
+            self.reparse(conf_todo)?;
+        }
+        Ok(())
+    }
+
+    /// Removes every occurrence of a tag or name.
+    fn remove_marker(&mut self, marker: &str, conf_todo: &TodoConfig) -> Result<(), TodoError> {
+        let mut spans: Vec<Range<usize>> = self
+            .tags
+            .iter()
+            .zip(&self.spans.tags)
+            .chain(self.names.iter().zip(&self.spans.names))
+            .filter(|(existing, _)| existing.eq_ignore_ascii_case(marker))
+            .map(|(_, span)| span.clone())
+            .collect();
+        // back to front, so the earlier spans stay valid
+        spans.sort_by_key(|span| std::cmp::Reverse(span.start));
+        for span in spans {
+            self.remove_span(span);
+        }
+        self.reparse(conf_todo)
+    }
+
+    /// Adds `text` to the end of the line, but before the stable ID.
+    fn insert_component(&mut self, text: &str) {
+        match &self.spans.stable_id {
+            Some(span) => self.raw.insert_str(span.start, &format!("{text} ")),
+            None => {
+                self.raw.truncate(self.raw.trim_end().len());
+                self.raw.push(' ');
+                self.raw.push_str(text);
+            }
+        }
+    }
+
+    /// The date of `kind`, for a due date only the day.
+    pub fn date(&self, kind: DateKind) -> Option<NaiveDate> {
+        match kind {
+            DateKind::Due => self.date_due.map(|due| due.date()),
+            DateKind::Scheduled => self.date_scheduled,
+            DateKind::Start => self.date_start,
+            DateKind::Created => self.date_created,
+            DateKind::Done => self.date_done,
+        }
+    }
+
+    /// Checks whether the date of `kind` is written in `raw`, not on a Logseq line below it.
+    pub fn has_inline_date(&self, kind: DateKind) -> bool {
+        self.date_span(kind).is_some()
+    }
+
+    fn date_span(&self, kind: DateKind) -> Option<&Range<usize>> {
+        match kind {
+            DateKind::Due => self.spans.date_due.as_ref(),
+            DateKind::Scheduled => self.spans.date_scheduled.as_ref(),
+            DateKind::Start => self.spans.date_start.as_ref(),
+            DateKind::Created => self.spans.date_created.as_ref(),
+            DateKind::Done => self.spans.date_done.as_ref(),
+        }
+    }
+
+    /// Stores a date found at `span` while parsing. Only due dates keep their time.
+    fn store_date(&mut self, kind: DateKind, due: Due, span: Range<usize>) {
+        match kind {
+            DateKind::Due => {
+                self.date_due = Some(due);
+                self.spans.date_due = Some(span);
+            }
+            DateKind::Scheduled => {
+                self.date_scheduled = Some(due.date());
+                self.spans.date_scheduled = Some(span);
+            }
+            DateKind::Start => {
+                self.date_start = Some(due.date());
+                self.spans.date_start = Some(span);
+            }
+            DateKind::Created => {
+                self.date_created = Some(due.date());
+                self.spans.date_created = Some(span);
+            }
+            DateKind::Done => {
+                self.date_done = Some(due.date());
+                self.spans.date_done = Some(span);
+            }
+        }
+    }
+
+    /// Sets or removes the date of `kind`. Existing dates keep their notation,
+    /// new ones are written with their marker like `⏳ 2024-07-05`, due dates without one.
+    /// Dates from Logseq's `DEADLINE:` and `SCHEDULED:` lines are only changed here,
+    /// `TodoDocument::update_task` writes them back to their line. Only due dates keep their time.
+    pub fn set_date(
+        &mut self,
+        kind: DateKind,
+        date: Option<Due>,
+        conf_todo: &TodoConfig,
+    ) -> Result<(), TodoError> {
+        let text = |date: Due| match kind {
+            DateKind::Due => date.to_string(),
+            _ => date.date().to_string(),
+        };
+        match (self.date_span(kind).cloned(), date) {
+            // `2024-07-05 14:00` stays written with a space
+            (Some(span), Some(date)) if self.raw[span.clone()].get(10..11) == Some(" ") => {
+                self.replace_span(span, &text(date).replacen('T', " ", 1))
+            }
+            (Some(span), Some(date)) => self.replace_span(span, &text(date)),
+            (Some(span), None) => self.remove_span(self.spans.with_marker(&span)),
+            (None, date) if kind == DateKind::Due && self.date_due.is_some() => {
+                self.date_due = date;
+                return Ok(());
+            }
+            (None, date) if kind == DateKind::Scheduled && self.date_scheduled.is_some() => {
+                self.date_scheduled = date.map(|date| date.date());
+                return Ok(());
+            }
+            (None, Some(date)) if kind == DateKind::Due => self.insert_component(&text(date)),
+            (None, Some(date)) => {
+                self.insert_component(&format!("{} {}", kind.marker(), text(date)))
+            }
+            (None, None) => return Ok(()),
+        }
+        self.reparse(conf_todo)
+    }
+
+    /// Sets or removes the completion date, see `set_date`.
+    /// On an open task, todo.txt's creation date would be taken for the due date without the
+    /// completion date in front of it, so it gets a `➕` when the completion date is removed.
+    pub fn set_done_date(
+        &mut self,
+        date: Option<NaiveDate>,
+        conf_todo: &TodoConfig,
+    ) -> Result<(), TodoError> {
+        match (self.spans.date_created.clone(), self.date_created) {
+            (Some(span), Some(created)) if date.is_none() && self.has_todotxt_done_date() => {
+                // back to front, so the completion date's span stays valid
+                self.remove_span(span);
+                if let Some(done) = self.spans.date_done.clone() {
+                    self.remove_span(done);
+                }
+                self.insert_component(&format!("{} {created}", DateKind::Created.marker()));
+                self.reparse(conf_todo)
+            }
+            _ => self.set_date(DateKind::Done, date.map(Due::Date), conf_todo),
+        }
+    }
+
+    /// Checks whether the completion date is written like in todo.txt, right after the checkbox.
+    pub fn has_todotxt_done_date(&self) -> bool {
+        self.spans
+            .date_done
+            .as_ref()
+            .is_some_and(|span| self.spans.with_marker(span) == *span)
+    }
+
+    /// The next occurrence of a recurring task that was completed `today`: open again,
+    /// with its dates moved ahead by the rule and a stable ID that isn't in `taken`.
+    /// `None` if the task doesn't recur, or its rule has no date left.
+    pub fn next_occurrence(
+        &self,
+        today: NaiveDate,
+        taken: &[&str],
+        conf_todo: &TodoConfig,
+    ) -> Result<Option<TodoItem>, TodoError> {
+        let Some(rule) = self.recurrence else {
+            return Ok(None);
+        };
+        let mut next = self.clone();
+        next.set_completed(false, conf_todo);
+        next.set_date(DateKind::Done, None, conf_todo)?;
+        if next.date_created.is_some() {
+            next.set_date(DateKind::Created, Some(Due::Date(today)), conf_todo)?;
+        }
+
+        // like Obsidian Tasks: the first of due, scheduled and start date is moved by the rule,
+        // the others keep their distance to it
+        let movable = [DateKind::Due, DateKind::Scheduled, DateKind::Start];
+        match movable.iter().find_map(|kind| self.date(*kind)) {
+            Some(reference) => {
+                let from = if rule.when_done { today } else { reference };
+                let Some(next_date) = rule.next(from) else {
+                    return Ok(None);
+                };
+                let days = (next_date - reference).num_days();
+                for kind in movable {
+                    let moved = match kind {
+                        DateKind::Due => self.date_due.and_then(|due| due.add_days(days)),
+                        _ => self
+                            .date(kind)
+                            .and_then(|date| Due::Date(date).add_days(days)),
+                    };
+                    if moved.is_some() {
+                        next.set_date(kind, moved, conf_todo)?;
+                    }
+                }
+            }
+            None => {
+                let Some(due) = rule.next(today) else {
+                    return Ok(None);
+                };
+                next.set_date(DateKind::Due, Some(Due::Date(due)), conf_todo)?;
+            }
+        }
+
+        next.assign_stable_id(taken, conf_todo)?;
+        next.id = 0;
+        next.line = 0;
+        next.children.clear();
+        Ok(Some(next))
+    }
+
+    /// Logseq's `SCHEDULED:` and `DEADLINE:` lines for the dates that aren't part of `raw`,
+    /// indented like the content of the task's block.
+    pub fn logseq_lines(&self) -> Vec<String> {
+        let indent = &self.raw[..self.raw.len() - self.raw.trim_start().len()];
+        let mut lines: Vec<String> = Vec::new();
+        if let (Some(date), None) = (self.date_scheduled, &self.spans.date_scheduled) {
+            lines.push(format!(
+                "{indent}  SCHEDULED: <{}>",
+                logseq_date_text(Due::Date(date))
+            ));
+        }
+        if let (Some(due), None) = (self.date_due, &self.spans.date_due) {
+            lines.push(format!("{indent}  DEADLINE: <{}>", logseq_date_text(due)));
+        }
+        lines
+    }
+
+    /// Gives the task a new stable ID that isn't in `taken`, replacing the one it has.
+    pub fn assign_stable_id(
+        &mut self,
+        taken: &[&str],
+        conf_todo: &TodoConfig,
+    ) -> Result<(), TodoError> {
+        let stable_id = format!("^{}", new_stable_id(taken));
+        match self.spans.stable_id.clone() {
+            Some(span) => self.replace_span(span, &stable_id),
+            None => self.insert_component(&stable_id),
+        }
+        self.reparse(conf_todo)
+    }
+
+    /// Cuts `span` out of the raw line together with the space separating it from the rest.
+    fn remove_span(&mut self, span: Range<usize>) {
+        let (mut start, mut end) = (span.start, span.end);
+        if self.raw[..start].ends_with(' ') {
+            start -= 1;
+        } else if self.raw[end..].starts_with(' ') {
+            end += 1;
+        }
+        self.raw.replace_range(start..end, "");
+    }
+
+    /// Updates all parsed fields after `raw` was changed. Where the task is stays the same.
+    fn reparse(&mut self, conf_todo: &TodoConfig) -> Result<(), TodoError> {
+        let parsed = parse_line(&self.raw, conf_todo)
+            .ok_or_else(|| TodoError::InvalidTask(self.raw.clone()))?;
+        self.stable_id = parsed.stable_id;
+        self.is_completed = parsed.is_completed;
+        self.title = parsed.title;
+        // dates from Logseq's `DEADLINE:` and `SCHEDULED:` lines aren't part of `raw`
+        if self.spans.date_due.is_some() || parsed.date_due.is_some() {
+            self.date_due = parsed.date_due;
+        }
+        if self.spans.date_scheduled.is_some() || parsed.date_scheduled.is_some() {
+            self.date_scheduled = parsed.date_scheduled;
+        }
+        self.date_start = parsed.date_start;
+        self.date_created = parsed.date_created;
+        self.date_done = parsed.date_done;
+        self.priority = parsed.priority;
+        self.recurrence = parsed.recurrence;
+        self.tags = parsed.tags;
+        self.names = parsed.names;
+        self.spans = parsed.spans;
+        Ok(())
+    }
+
+    /// Checks whether `selector` refers to this task, either by its positional or its stable ID.
+    pub fn matches_selector(&self, selector: &str) -> bool {
+        match selector.parse::<usize>() {
+            Ok(id) => self.id == id,
+            Err(_) => self.stable_id.as_deref() == Some(selector.trim_start_matches('^')),
+        }
+    }
+
+    /// Checks whether any of the headings the task lives under is called `name`.
+    pub fn in_section(&self, name: &str) -> bool {
+        self.section
+            .iter()
+            .any(|heading| heading.eq_ignore_ascii_case(name))
+    }
+
+    /// Writes `todoitem` as a line, keeping the notation of what was parsed from `raw`.
+    pub fn get_string(todoitem: &TodoItem, conf_todo: &TodoConfig) -> String {
+        let mut result_string = String::new();
+        result_string.push_str(if todoitem.is_completed {
+            &conf_todo.example_done
+        } else {
+            &conf_todo.example_todo
+        });
+
+        // keep the notation the priority and dates were written in,
+        // todo.txt's `(A)` and its dates without a marker have to come first
+        let priority_marker = match (&todoitem.spans.priority, todoitem.priority) {
+            (Some(span), _) => todoitem.raw.get(span.clone()),
+            (None, priority) => priority.map(|priority| priority.marker()),
+        };
+        let mut leading: Vec<String> = Vec::new();
+        let mut trailing: Vec<String> = Vec::new();
+        for kind in [
+            DateKind::Due,
+            DateKind::Scheduled,
+            DateKind::Start,
+            DateKind::Created,
+            DateKind::Done,
+        ] {
+            let written = todoitem.date_span(kind).and_then(|span| {
+                let with_marker = todoitem.spans.with_marker(span);
+                let text = todoitem.raw.get(with_marker.clone())?.to_string();
+                Some((text, with_marker == *span))
+            });
+            match (written, kind) {
+                // todo.txt puts the completion date before the creation date
+                (Some((text, true)), DateKind::Done | DateKind::Created) => leading.insert(0, text),
+                (Some((text, _)), _) => trailing.push(text),
+                (None, DateKind::Due) => {
+                    trailing.extend(todoitem.date_due.map(|due| due.to_string()))
+                }
+                (None, kind) => trailing.extend(
+                    todoitem
+                        .date(kind)
+                        .map(|date| format!("{} {date}", kind.marker())),
+                ),
+            }
+        }
+
+        if let Some(marker) = priority_marker.filter(|marker| marker.starts_with('(')) {
+            result_string.push_str(&format!(" {marker}"));
+        }
+        for date in leading {
+            result_string.push_str(&format!(" {date}"));
+        }
+        result_string.push(' ');
+        result_string.push_str(&todoitem.title);
+        if let Some(marker) = priority_marker.filter(|marker| !marker.starts_with('(')) {
+            result_string.push_str(&format!(" {marker}"));
+        }
+        match (&todoitem.spans.recurrence, todoitem.recurrence) {
+            (Some(span), _) if todoitem.raw.get(span.clone()).is_some() => {
+                result_string.push_str(&format!(" {}", &todoitem.raw[span.clone()]));
+            }
+            (_, Some(rule)) => result_string.push_str(&format!(" 🔁 {rule}")),
+            (_, None) => (),
+        }
+        // e.g. `2024-07-05 14:00` or `⏳ 2024-07-05`
+        for date in trailing {
+            result_string.push_str(&format!(" {date}"));
+        }
+        for tag in &todoitem.tags {
+            result_string.push_str(&format!(" {tag}"))
+        }
+        for name in &todoitem.names {
+            result_string.push_str(&format!(" {name}"))
+        }
+        if let Some(stable_id) = &todoitem.stable_id {
+            result_string.push_str(&format!(" ^{stable_id}"))
+        }
+
+        result_string
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigFile;
+
+    fn md() -> TodoConfig {
+        TodoConfig::new(&ConfigFile::default())
+    }
+
+    fn date(input: &str) -> NaiveDate {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn components() {
+        let item = parse_line(
+            "  - [ ] call mom ⏫ 📅 2024-07-05 #family @alice ^k3x9qa",
+            &md(),
+        )
+        .unwrap();
+        assert_eq!(item.title, "call mom");
+        assert_eq!(item.priority, Some(Priority::High));
+        assert_eq!(item.date_due, Some(Due::Date(date("2024-07-05"))));
+        assert_eq!(item.tags, vec!["#family"]);
+        assert_eq!(item.names, vec!["@alice"]);
+        assert_eq!(item.stable_id.as_deref(), Some("k3x9qa"));
+        assert!(!item.is_completed);
+    }
+
+    #[test]
+    fn not_a_task() {
+        assert!(parse_line("- call mom", &md()).is_none());
+        assert!(parse_line("call mom", &md()).is_none());
+    }
+
+    #[test]
+    fn first_unmarked_date_is_due() {
+        let item = parse_line("- [ ] 2024-07-05 2024-07-10 plan trip", &md()).unwrap();
+        assert_eq!(item.date_due, Some(Due::Date(date("2024-07-05"))));
+        assert_eq!(item.date_done, None);
+        assert_eq!(item.date_created, None);
+
+        // not a valid date, so it's part of the title
+        let item = parse_line("- [ ] fix 2024-13-45", &md()).unwrap();
+        assert_eq!(item.title, "fix 2024-13-45");
+        assert_eq!(item.date_due, None);
+    }
+
+    #[test]
+    fn todotxt_dates() {
+        let done = parse_line("- [x] 2024-07-06 2024-07-01 call mom 2024-07-05", &md()).unwrap();
+        assert_eq!(done.date_done, Some(date("2024-07-06")));
+        assert_eq!(done.date_created, Some(date("2024-07-01")));
+        assert_eq!(done.date_due, Some(Due::Date(date("2024-07-05"))));
+
+        let open = parse_line("- [ ] (A) 2024-07-01 call mom", &md()).unwrap();
+        assert_eq!(open.priority, Some(Priority::Highest));
+        assert_eq!(open.date_created, Some(date("2024-07-01")));
+        assert_eq!(open.date_due, None);
+    }
+
+    #[test]
+    fn marked_dates() {
+        let item = parse_line(
+            "- [ ] call mom ⏳ 2024-07-03 t:2024-07-02 ➕ 2024-07-01 due:2024-07-05T14:00",
+            &md(),
+        )
+        .unwrap();
+        assert_eq!(item.title, "call mom");
+        assert_eq!(item.date_scheduled, Some(date("2024-07-03")));
+        assert_eq!(item.date_start, Some(date("2024-07-02")));
+        assert_eq!(item.date_created, Some(date("2024-07-01")));
+        assert_eq!(item.date_due, Due::parse("2024-07-05T14:00"));
+    }
+
+    #[test]
+    fn unchecking_moves_the_todotxt_creation_date() {
+        let conf_todo = md();
+        let mut item = parse_line("- [x] 2024-07-06 2024-07-01 call mom", &conf_todo).unwrap();
+        item.set_completed(false, &conf_todo);
+        item.set_done_date(None, &conf_todo).unwrap();
+        assert_eq!(item.raw, "- [ ] call mom ➕ 2024-07-01");
+        assert_eq!(item.date_created, Some(date("2024-07-01")));
+        assert_eq!(item.date_due, None);
+    }
+
+    #[test]
+    fn recurrence_takes_the_longest_rule() {
+        let item = parse_line("- [ ] review every 2 weeks on friday #work", &md()).unwrap();
+        assert_eq!(item.title, "review");
+        assert_eq!(
+            item.recurrence.map(|rule| rule.to_string()).as_deref(),
+            Some("every 2 weeks on friday")
+        );
+    }
+
+    #[test]
+    fn next_occurrence_moves_all_dates() {
+        let conf_todo = md();
+        let item = parse_line(
+            "- [x] review ⏳ 2024-07-03 📅 2024-07-05 🔁 every week ^k3x9qa",
+            &conf_todo,
+        )
+        .unwrap();
+        let next = item
+            .next_occurrence(date("2024-07-04"), &["k3x9qa"], &conf_todo)
+            .unwrap()
+            .unwrap();
+        assert!(!next.is_completed);
+        assert_eq!(next.date_due, Some(Due::Date(date("2024-07-12"))));
+        assert_eq!(next.date_scheduled, Some(date("2024-07-10")));
+        assert_ne!(next.stable_id, item.stable_id);
+    }
+
+    #[test]
+    fn next_occurrence_keeps_logseq_dates_on_their_lines() {
+        let mut conf_file = ConfigFile::default();
+        conf_file.format.checkbox_style = "logseq".to_string();
+        let conf_todo = TodoConfig::new(&conf_file);
+        let lines = ["- DONE pay rent rec:+1m", "  DEADLINE: <2024-07-01 Mon>"];
+        let tasks = strings_to_todo(
+            lines.iter().map(|line| line.to_string()).collect(),
+            Path::new("todo.md"),
+            &conf_todo,
+        );
+        let next = tasks[0]
+            .next_occurrence(date("2024-07-04"), &[], &conf_todo)
+            .unwrap()
+            .unwrap();
+        assert!(next.raw.starts_with("- TODO pay rent rec:+1m ^"));
+        assert_eq!(next.date_due, Some(Due::Date(date("2024-08-01"))));
+        assert_eq!(next.logseq_lines(), vec!["  DEADLINE: <2024-08-01 Thu>"]);
+    }
+
+    #[test]
+    fn sections() {
+        let lines = [
+            "# Work",
+            "- [ ] a",
+            "## Backend",
+            "- [ ] b",
+            "# Home",
+            "- [ ] c",
+        ];
+        let tasks = strings_to_todo(
+            lines.iter().map(|line| line.to_string()).collect(),
+            Path::new("todo.md"),
+            &md(),
+        );
+        assert_eq!(tasks[0].section, vec!["Work"]);
+        assert_eq!(tasks[1].section, vec!["Work", "Backend"]);
+        assert_eq!(tasks[2].section, vec!["Home"]);
+        assert!(tasks[1].in_section("backend"));
+        assert!(tasks[1].in_section("work"));
+    }
+
+    #[test]
+    fn new_tasks_go_before_subsections() {
+        let lines: Vec<String> = ["## Work", "- [ ] a", "", "### Backend", "- [ ] b"]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(find_section_end(&lines, "work"), Some(2));
+        assert_eq!(find_section_end(&lines, "Backend"), Some(5));
+        assert_eq!(find_section_end(&lines, "Home"), None);
+    }
+
+    #[test]
+    fn subtasks() {
+        let lines = [
+            "- [ ] a",
+            "  - [ ] b",
+            "    - [ ] c",
+            "  - [ ] d",
+            "text",
+            "  - [ ] e",
+        ];
+        let tasks = strings_to_todo(
+            lines.iter().map(|line| line.to_string()).collect(),
+            Path::new("todo.md"),
+            &md(),
+        );
+        assert_eq!(tasks[0].children, vec![2, 4]);
+        assert_eq!(tasks[1].children, vec![3]);
+        assert_eq!((tasks[2].parent, tasks[2].depth), (Some(2), 2));
+        assert_eq!((tasks[3].parent, tasks[3].depth), (Some(1), 1));
+        // a paragraph ends the list
+        assert_eq!((tasks[4].parent, tasks[4].depth), (None, 0));
+    }
+
+    #[test]
+    fn edits_keep_the_rest_of_the_line() {
+        let conf_todo = md();
+        let mut item = parse_line(
+            "- [ ] call   mom 2024-07-05 14:00 #family  @bob ^k3x9qa",
+            &conf_todo,
+        )
+        .unwrap();
+        let edit = TaskEdit {
+            due: Some(Due::parse("2024-07-06T09:30")),
+            add_tags: vec!["urgent".to_string()],
+            unassign: vec!["bob".to_string()],
+            ..TaskEdit::default()
+        };
+        item.apply(&edit, &conf_todo).unwrap();
+        assert_eq!(
+            item.raw,
+            "- [ ] call   mom 2024-07-06 09:30 #family  #urgent ^k3x9qa"
+        );
+        assert_eq!(item.tags, vec!["#family", "#urgent"]);
+        assert!(item.names.is_empty());
+    }
+}
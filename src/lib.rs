@@ -0,0 +1,70 @@
+//! Parse and edit tasks stored in Markdown files, as used by the `todo` command line tool.
+//!
+//! Nothing in here prints or asks for input, so it can be used from other tools as well:
+//!
+//! ```
+//! use todo_md::*;
+//!
+//! let conf_todo = TodoConfig::new(&ConfigFile::default());
+//! let lines = vec![
+//!     "## Work".to_string(),
+//!     "- [ ] send invoice 2024-07-05 #billing @alice".to_string(),
+//! ];
+//! let tasks = strings_to_todo(lines, std::path::Path::new("todo.md"), &conf_todo);
+//!
+//! assert_eq!(tasks[0].title, "send invoice");
+//! assert_eq!(tasks[0].section, vec!["Work"]);
+//! assert_eq!(tasks[0].tags, vec!["#billing"]);
+//! ```
+//!
+//! Changes are made through a [`TodoDocument`], which collects them and writes the file once.
+//! A [`Workspace`] does the same for several files.
+//!
+//! ```no_run
+//! use todo_md::*;
+//!
+//! let conf_file = ConfigFile::default();
+//! let conf_todo = TodoConfig::new(&conf_file);
+//! let _lock = FileLock::acquire(&conf_file.todo_file(), std::time::Duration::from_secs(5))?;
+//! let mut document = TodoDocument::load(&conf_file.todo_file())?;
+//!
+//! let mut tasks = document.tasks(&conf_todo);
+//! document.set_completed(&mut tasks[0], true, &conf_todo);
+//! document.save()?;
+//! # Ok::<(), TodoError>(())
+//! ```
+
+#![warn(missing_docs)]
+
+mod config;
+mod dates;
+mod document;
+mod error;
+mod index;
+mod item;
+mod lock;
+mod priority;
+mod query;
+mod recurrence;
+#[cfg(test)]
+mod testing;
+mod vault;
+mod view;
+mod workspace;
+
+pub use config::{
+    discover, ConfigFile, Discovered, TodoBehavior, TodoConfig, TodoFormatting, TodoPath,
+    CHECKBOX_STYLES, LOCAL_CONFIG, LOCAL_TODO_FILE,
+};
+pub use dates::{parse_date, parse_due, DateKind, Due};
+pub use document::TodoDocument;
+pub use error::TodoError;
+pub use index::TaskIndex;
+pub use item::{parse_line, strings_to_todo, TaskEdit, TodoItem, TodoSpans};
+pub use lock::{FileLock, DIR_LOCK};
+pub use priority::Priority;
+pub use query::{DueFilter, PriorityFilter, Query};
+pub use recurrence::{Recurrence, RecurrenceDay, RecurrenceUnit};
+pub use vault::{is_logseq_graph, vault_files};
+pub use view::{builtin_views, GroupKey, SortKey, ViewConfig};
+pub use workspace::Workspace;
@@ -0,0 +1,96 @@
+//! Keeping several processes from editing the same file at once.
+
+use crate::error::TodoError;
+use crate::vault::is_logseq_graph;
+use std::fs;
+use std::fs::{File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Name of the lock file shared by all notes of a vault, in its root.
+pub const DIR_LOCK: &str = ".todo.lock";
+
+/// Advisory lock that keeps other `todo` processes from editing the same file until it's dropped.
+/// The lock is taken on a separate `.<filename>.lock` file, as the todo file itself gets replaced on save.
+/// Notes in a vault share the `.todo.lock` of the vault instead, see `lock_path`.
+pub struct FileLock {
+    _file: File,
+}
+impl FileLock {
+    /// Waits up to `timeout` for other processes to release the lock of `path`, a todo file or a vault.
+    pub fn acquire(path: &Path, timeout: Duration) -> Result<FileLock, TodoError> {
+        FileLock::lock(path, FileLock::lock_path(path), timeout)
+    }
+
+    /// The file the lock of `path` is taken on. Files and directories inside a vault all use the
+    /// `.todo.lock` of the outermost vault, no matter if they're reached through it or on their own.
+    /// A vault is recognised by its `.todo.lock`, or as an Obsidian vault or a Logseq graph.
+    /// Any other directory gets a `.todo.lock`, any other file a `.<filename>.lock` next to it.
+    pub fn lock_path(path: &Path) -> PathBuf {
+        let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let vault = target.ancestors().filter(|dir| is_vault(dir)).last();
+        if let Some(vault) = vault {
+            return vault.join(DIR_LOCK);
+        }
+        if target.is_dir() {
+            return target.join(DIR_LOCK);
+        }
+        let file_name = target
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        target.with_file_name(format!(".{file_name}.lock"))
+    }
+
+    fn lock(path: &Path, lock_path: PathBuf, timeout: Duration) -> Result<FileLock, TodoError> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(TodoError::io(&lock_path))?;
+
+        let start = Instant::now();
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(FileLock { _file: file }),
+                Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                    thread::sleep(Duration::from_millis(50))
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(TodoError::Locked {
+                        path: path.to_path_buf(),
+                        lock_path,
+                    })
+                }
+                Err(TryLockError::Error(e)) => return Err(TodoError::io(lock_path)(e)),
+            }
+        }
+    }
+}
+
+fn is_vault(dir: &Path) -> bool {
+    dir.join(DIR_LOCK).is_file() || dir.join(".obsidian").is_dir() || is_logseq_graph(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn notes_share_the_lock_of_their_vault() {
+        let dir = TempDir::new("lock");
+        let note = dir.write("vault/sub/note.md", "");
+        let vault = dir.path().join("vault");
+
+        assert_eq!(FileLock::lock_path(&note), vault.join("sub/.note.md.lock"));
+        let _vault_lock = FileLock::acquire(&vault, Duration::ZERO).unwrap();
+        assert_eq!(FileLock::lock_path(&note), vault.join(DIR_LOCK));
+        assert!(matches!(
+            FileLock::acquire(&note, Duration::ZERO),
+            Err(TodoError::Locked { .. })
+        ));
+    }
+}
@@ -1,62 +1,446 @@
+mod editor;
+mod output;
 mod todo;
+use crate::output::OutputFormat;
 use crate::todo::*;
+use chrono::Local;
 use clap::{Parser, Subcommand};
+use colored::Colorize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use todo_md::*;
+
+/// how long to wait for other todo processes working on the same files
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Parser, Debug)]
 struct Args {
     #[command(subcommand)]
     command: Option<Commands>,
+    /// answer every question with yes
+    #[arg(short, long, global = true)]
+    yes: bool,
+    /// never ask questions, fail instead (the default if stdin is not a terminal)
+    #[arg(long, global = true)]
+    no_input: bool,
+    /// use this todo file instead of the configured or discovered one
+    #[arg(short, long, global = true, env = "TODO_FILE")]
+    file: Option<PathBuf>,
+    /// also include every note in this directory (an Obsidian vault or Logseq graph)
+    #[arg(long, global = true, value_name = "DIR")]
+    vault: Option<PathBuf>,
+    /// how to print tasks and errors
+    #[arg(short, long, global = true, value_enum, default_value_t)]
+    output: OutputFormat,
 }
 
 #[derive(Debug, Subcommand)]
 enum Commands {
-    /// lists all tasks
+    /// lists all tasks, or those matching a query
     #[clap(alias = "ls")]
-    List {},
+    List {
+        /// only list tasks under the heading with this name
+        #[arg(short, long)]
+        section: Option<String>,
+        /// filters like `+work @alice due:<2024-07-10 -done`, combined with and/or/not and parentheses
+        #[arg(allow_hyphen_values = true)]
+        query: Vec<String>,
+        /// due, priority, created, title or file
+        #[arg(long)]
+        sort: Option<SortKey>,
+        /// tag, name, section, date or file
+        #[arg(long, short)]
+        group_by: Option<GroupKey>,
+    },
     /// adds a task
     #[clap(alias = "a")]
     Add {
+        /// append the task to the end of the heading with this name
+        #[arg(short, long)]
+        section: Option<String>,
+        /// due date, e.g. 2024-07-05, fri, +3d, "in 2 weeks" or "end of month"
+        #[arg(long, allow_hyphen_values = true)]
+        due: Option<String>,
+        /// highest, high, medium, low or lowest
+        #[arg(short, long)]
+        priority: Option<Priority>,
         /// title and other properties of the task to be added
         content: Vec<String>,
     },
     /// remove one or more tasks
     #[clap(alias = "rm")]
     Remove {
-        /// IDs of the tasks to remove
-        ids: Vec<usize>,
+        /// IDs of the tasks to remove (positional or stable)
+        ids: Vec<String>,
     },
     /// check off a task
     #[clap(alias = "d")]
     Done {
-        /// IDs of the tasks to mark done
-        ids: Vec<usize>,
+        /// IDs of the tasks to mark done (positional or stable)
+        ids: Vec<String>,
+        /// also check off all subtasks
+        #[arg(short, long)]
+        recursive: bool,
     },
     /// uncheck a task that is already done
     #[clap(alias = "u")]
     Uncheck {
-        /// IDs of the tasks to mark todo
-        ids: Vec<usize>,
+        /// IDs of the tasks to mark todo (positional or stable)
+        ids: Vec<String>,
+    },
+    /// change an existing task
+    #[clap(alias = "e")]
+    Edit {
+        /// ID of the task to change (positional or stable)
+        id: String,
+        /// edit the line of the task in $VISUAL or $EDITOR
+        #[arg(short, long, conflicts_with_all = ["content", "due", "priority", "add_tag", "rm_tag", "assign", "unassign"])]
+        interactive: bool,
+        /// new title and other properties, replacing the old ones
+        content: Vec<String>,
+        /// set the due date (e.g. 2024-07-05, tomorrow, fri, +3d, "next week") or remove it with "none"
+        #[arg(long, allow_hyphen_values = true)]
+        due: Option<String>,
+        /// set the priority (highest, high, medium, low, lowest) or remove it with "none"
+        #[arg(short, long)]
+        priority: Option<String>,
+        /// add a tag
+        #[arg(long, value_name = "TAG")]
+        add_tag: Vec<String>,
+        /// remove a tag
+        #[arg(long, value_name = "TAG")]
+        rm_tag: Vec<String>,
+        /// assign someone
+        #[arg(long, value_name = "NAME")]
+        assign: Vec<String>,
+        /// unassign someone
+        #[arg(long, value_name = "NAME")]
+        unassign: Vec<String>,
     },
+    /// open the file of a task in $VISUAL or $EDITOR, at the line of the task
+    #[clap(alias = "o")]
+    Open {
+        /// ID of the task (positional or stable)
+        id: String,
+    },
+    /// lists the tasks of a saved query, or all saved queries
+    View {
+        /// name of the view, e.g. `today`
+        name: Option<String>,
+        /// more filters to narrow the view down
+        #[arg(allow_hyphen_values = true)]
+        query: Vec<String>,
+        /// due, priority, created, title or file, instead of the view's sorting
+        #[arg(long)]
+        sort: Option<SortKey>,
+        /// tag, name, section, date or file, instead of the view's grouping
+        #[arg(long, short)]
+        group_by: Option<GroupKey>,
+    },
+    /// parse all todo files again instead of using the cached tasks
+    Reindex,
 }
 
 fn main() {
     let args = Args::parse();
 
+    if let Err(e) = run(&args) {
+        if args.output == OutputFormat::Text {
+            eprintln!("{} {e}", "error:".red());
+            if let Some(hint) = hint(&e) {
+                eprintln!("{hint}");
+            }
+        } else {
+            let error = serde_json::json!({
+                "error": e.to_string(),
+                "hint": hint(&e),
+                "exit_code": exit_code(&e),
+            });
+            eprintln!("{error}");
+        }
+        std::process::exit(exit_code(&e));
+    }
+}
+
+fn run(args: &Args) -> Result<(), TodoError> {
     // set up the configuration
-    let conf_file: ConfigFile = ConfigFile::init();
+    let prompt = Prompt::new(args.yes, args.no_input, args.output);
+    let mut conf_file: ConfigFile = init_config(&prompt, args.file.as_deref())?;
+    if let Some(vault) = &args.vault {
+        conf_file.set_vault(vault);
+    }
     let conf_todo = TodoConfig::new(&conf_file);
-    let complete_path = conf_file.path.todo_path.join(&conf_file.path.todo_filename); // TODO: nasty workaround but best until fixed
+    let todo_files = conf_file.todo_files()?;
+    let vault = conf_file.vault_dir();
+
+    // keep other todo processes out until we're done
+    let mut locks = lock_files(&todo_files, vault.as_deref())?;
 
-    // get the todos
     let mut todos = Todo::new(); // get empty parser
-    todos.todo_list = strings_to_todo(read_lines(&complete_path), &conf_todo); // populate parser
-
-    match &args.command {
-        Some(Commands::List {}) => todos.list_all(),
-        Some(Commands::Add { content }) => todos.add(content, &conf_todo, &conf_file),
-        Some(Commands::Remove { ids }) => todos.remove(ids, complete_path),
-        Some(Commands::Done { ids }) => todos.done(ids, &conf_file, &conf_todo),
-        Some(Commands::Uncheck { ids }) => todos.uncheck(ids, &conf_file, &conf_todo),
-        None => todos.list_all(),
+    todos.output = args.output;
+    if todo_files.len() > 1 {
+        todos.files_root = Some(
+            vault
+                .clone()
+                .unwrap_or_else(|| conf_file.path.todo_path.clone()),
+        );
+    }
+    let default_command = Commands::List {
+        section: None,
+        query: Vec::new(),
+        sort: None,
+        group_by: None,
+    };
+    let command = args.command.as_ref().unwrap_or(&default_command);
+
+    // listing goes through the index, so files that didn't change aren't even read
+    match command {
+        Commands::List {
+            section,
+            query,
+            sort,
+            group_by,
+        } => {
+            let mut query = Query::from_args(query, Local::now().date_naive())?;
+            if let Some(section) = section {
+                query = query.and(Query::Section(section.clone()));
+            }
+            todos.todo_list = indexed_tasks(&todo_files, &conf_file, &conf_todo, false)?;
+            if let Some(sort) = sort {
+                sort.sort(&mut todos.todo_list);
+            }
+            todos.list_all(&query, *group_by);
+            todos.finish();
+            return Ok(());
+        }
+        Commands::Open { id } => {
+            todos.todo_list = indexed_tasks(&todo_files, &conf_file, &conf_todo, false)?;
+            let item = todos.find(id)?;
+            // the editor may stay open for a while, other todo processes shouldn't wait for it
+            drop(locks);
+            editor::open_at(&item.file, item.line)?;
+            todos.finish();
+            return Ok(());
+        }
+        Commands::View { name: None, .. } => {
+            list_views(&conf_file.views(), &todos);
+            todos.finish();
+            return Ok(());
+        }
+        Commands::View {
+            name: Some(name),
+            query,
+            sort,
+            group_by,
+        } => {
+            let view = conf_file
+                .views()
+                .remove(name)
+                .ok_or_else(|| TodoError::ViewNotFound(name.clone()))?;
+            let today = Local::now().date_naive();
+            let query = Query::parse(&view.query, today)?.and(Query::from_args(query, today)?);
+            todos.todo_list = indexed_tasks(&todo_files, &conf_file, &conf_todo, false)?;
+            if let Some(sort) = sort.or(view.sort) {
+                sort.sort(&mut todos.todo_list);
+            }
+            todos.list_all(&query, group_by.or(view.group_by));
+            todos.finish();
+            return Ok(());
+        }
+        Commands::Reindex => {
+            todos.todo_list = indexed_tasks(&todo_files, &conf_file, &conf_todo, true)?;
+            if args.output == OutputFormat::Text {
+                println!(
+                    "indexed {} tasks in {} files",
+                    todos.todo_list.len(),
+                    todo_files.len()
+                );
+            } else {
+                todos.emit(serde_json::json!({
+                    "action": "reindexed",
+                    "tasks": todos.todo_list.len(),
+                    "files": todo_files.len(),
+                }));
+            }
+            todos.finish();
+            return Ok(());
+        }
+        _ => (),
+    }
+
+    // get the todos
+    let mut workspace = Workspace::load(&todo_files, vault.as_deref())?;
+    warn_skipped(&workspace.skipped);
+    todos.todo_list = workspace.tasks(&conf_todo); // populate parser
+
+    match command {
+        Commands::Add {
+            content,
+            section,
+            due,
+            priority,
+        } => {
+            let properties = TaskEdit {
+                due: due
+                    .as_deref()
+                    .map(|date| parse_due(date, Local::now().date_naive()).map(Some))
+                    .transpose()?,
+                priority: priority.map(Some),
+                ..TaskEdit::default()
+            };
+            todos.add(
+                content,
+                section.as_deref(),
+                &properties,
+                &conf_todo,
+                &mut workspace,
+            )?
+        }
+        Commands::Remove { ids } => todos.remove(
+            ids,
+            conf_file.behavior.confirm_remove,
+            &prompt,
+            &conf_todo,
+            &mut workspace,
+        )?,
+        Commands::Done { ids, recursive } => todos.done(
+            ids,
+            *recursive,
+            conf_file.behavior.stamp_done_date,
+            &conf_todo,
+            &mut workspace,
+        )?,
+        Commands::Uncheck { ids } => todos.uncheck(
+            ids,
+            conf_file.behavior.stamp_done_date,
+            &conf_todo,
+            &mut workspace,
+        )?,
+        Commands::Edit {
+            id,
+            interactive: true,
+            ..
+        } => {
+            // like `open`, other todo processes shouldn't wait for the editor.
+            // Should they change the task meanwhile, saving notices
+            drop(std::mem::take(&mut locks));
+            let line = todos.edit_in_editor(id)?;
+            locks = lock_files(&todo_files, vault.as_deref())?;
+            todos.edit_line(id, &line, &conf_todo, &mut workspace)?
+        }
+        Commands::Edit {
+            id,
+            interactive: false,
+            content,
+            due,
+            priority,
+            add_tag,
+            rm_tag,
+            assign,
+            unassign,
+        } => {
+            let edit = TaskEdit {
+                content: (!content.is_empty()).then(|| content.join(" ")),
+                due: match due.as_deref() {
+                    None => None,
+                    Some("none") => Some(None),
+                    Some(date) => Some(Some(parse_due(date, Local::now().date_naive())?)),
+                },
+                priority: match priority.as_deref() {
+                    None => None,
+                    Some("none") => Some(None),
+                    Some(level) => {
+                        Some(Some(level.parse().map_err(|_| {
+                            TodoError::InvalidPriority(level.to_string())
+                        })?))
+                    }
+                },
+                add_tags: add_tag.clone(),
+                remove_tags: rm_tag.clone(),
+                assign: assign.clone(),
+                unassign: unassign.clone(),
+            };
+            todos.edit(id, &edit, &conf_todo, &mut workspace)?
+        }
+        Commands::List { .. }
+        | Commands::Open { .. }
+        | Commands::View { .. }
+        | Commands::Reindex => (),
+    }
+
+    // write all changes at once
+    if workspace.is_modified() {
+        workspace.save()?;
+    }
+    drop(locks);
+    todos.finish();
+    Ok(())
+}
+
+/// Locks the vault and all files outside of it, waiting for other todo processes to finish.
+/// Files sharing a lock (see `FileLock::lock_path`) lock it once, always in the same order,
+/// so two processes can't wait for each other.
+fn lock_files(todo_files: &[PathBuf], vault: Option<&Path>) -> Result<Vec<FileLock>, TodoError> {
+    let outside_vault = todo_files
+        .iter()
+        .map(PathBuf::as_path)
+        .filter(|path| vault.is_none_or(|vault| !path.starts_with(vault)));
+    let mut lock_order: Vec<(PathBuf, &Path)> = vault
+        .into_iter()
+        .chain(outside_vault)
+        .map(|path| (FileLock::lock_path(path), path))
+        .collect();
+    lock_order.sort();
+    lock_order.dedup_by(|a, b| a.0 == b.0);
+    lock_order
+        .into_iter()
+        .map(|(_, path)| FileLock::acquire(path, LOCK_TIMEOUT))
+        .collect()
+}
+
+/// Every kind of error gets its own exit code so scripts can tell them apart.
+fn exit_code(error: &TodoError) -> i32 {
+    match error {
+        TodoError::Io { .. } => 3,
+        TodoError::Input(_) => 4,
+        TodoError::Config { .. } => 5,
+        TodoError::Conflict { .. } => 6,
+        TodoError::Locked { .. } => 7,
+        TodoError::TaskNotFound(_) => 8,
+        TodoError::SectionNotFound(_) => 9,
+        TodoError::InvalidTask(_) => 10,
+        TodoError::Aborted => 11,
+        TodoError::InputRequired(_) => 12,
+        TodoError::InvalidQuery(_) => 13,
+        TodoError::ViewNotFound(_) => 14,
+        TodoError::InvalidDate(_) => 15,
+        TodoError::InvalidPriority(_) => 16,
+    }
+}
+
+/// What the user can do about an error, if there's anything.
+fn hint(error: &TodoError) -> Option<String> {
+    match error {
+        TodoError::Config { .. } => {
+            Some("fix the file or delete it to create a new one with the defaults".to_string())
+        }
+        TodoError::Conflict { .. } => {
+            Some("run the command again to act on the current content".to_string())
+        }
+        TodoError::Locked { .. } => Some(format!(
+            "waited {}s for it to finish, another todo process is still running",
+            LOCK_TIMEOUT.as_secs()
+        )),
+        TodoError::TaskNotFound(_) => {
+            Some("run `todo list` to see the current IDs, nothing was changed".to_string())
+        }
+        TodoError::Aborted => Some("nothing was changed".to_string()),
+        TodoError::InputRequired(_) => Some("pass --yes to answer it automatically".to_string()),
+        TodoError::InvalidQuery(_) => Some("see `todo list --help` for the syntax".to_string()),
+        TodoError::ViewNotFound(_) => Some("run `todo view` to see all views".to_string()),
+        TodoError::InvalidPriority(_) => {
+            Some("use highest, high, medium, low, lowest or none".to_string())
+        }
+        _ => None,
     }
 }
@@ -0,0 +1,64 @@
+//! Output for other programs, selected with `--output`.
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::Path;
+use todo_md::{Priority, TodoItem};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// coloured, for humans
+    #[default]
+    Text,
+    /// a single JSON array
+    Json,
+    /// one JSON object per line
+    Ndjson,
+}
+
+/// The fields of a task in the JSON output. Kept separate from `TodoItem`
+/// so that internal changes don't break scripts.
+#[derive(Debug, Serialize)]
+pub struct TaskJson<'a> {
+    pub id: usize,
+    pub stable_id: Option<&'a str>,
+    pub file: &'a Path,
+    pub line: Option<usize>, // unknown for tasks that aren't in a file
+    pub title: &'a str,
+    pub completed: bool,
+    pub due: Option<String>, // `2024-07-05`, or with a time like in the file
+    pub scheduled: Option<NaiveDate>,
+    pub start: Option<NaiveDate>,
+    pub created: Option<NaiveDate>,
+    pub completed_on: Option<NaiveDate>,
+    pub priority: Option<Priority>,
+    pub recurrence: Option<String>, // like `every 2 weeks on monday`
+    pub tags: &'a [String],
+    pub names: &'a [String],
+    pub section: &'a [String],
+    pub parent: Option<usize>,
+}
+impl<'a> From<&'a TodoItem> for TaskJson<'a> {
+    fn from(item: &'a TodoItem) -> TaskJson<'a> {
+        TaskJson {
+            id: item.id,
+            stable_id: item.stable_id.as_deref(),
+            file: &item.file,
+            line: (item.line > 0).then_some(item.line),
+            title: &item.title,
+            completed: item.is_completed,
+            due: item.date_due.map(|due| due.to_string()),
+            scheduled: item.date_scheduled,
+            start: item.date_start,
+            created: item.date_created,
+            completed_on: item.date_done,
+            priority: item.priority,
+            recurrence: item.recurrence.map(|rule| rule.to_string()),
+            tags: &item.tags,
+            names: &item.names,
+            section: &item.section,
+            parent: item.parent,
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! How important a task is.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Priority of a task, in the five levels of Obsidian Tasks.
+/// Other notations are mapped onto them, see `from_marker`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// ⏬, or todo.txt's `(E)` to `(Z)`
+    Lowest,
+    /// 🔽 or `(D)`
+    Low,
+    /// 🔼, `!` or `(C)`
+    Medium,
+    /// ⏫, `!!` or `(B)`
+    High,
+    /// 🔺, `!!!` or `(A)`
+    Highest,
+}
+impl Priority {
+    /// Reads a marker found in a task line:
+    /// `!`, `!!`, `!!!` (todo.md), `(A)` to `(Z)` (todo.txt) or 🔺 ⏫ 🔼 🔽 ⏬ (Obsidian Tasks).
+    pub fn from_marker(marker: &str) -> Option<Priority> {
+        match marker {
+            "!!!" | "(A)" | "🔺" => Some(Priority::Highest),
+            "!!" | "(B)" | "⏫" => Some(Priority::High),
+            "!" | "(C)" | "🔼" => Some(Priority::Medium),
+            "(D)" | "🔽" => Some(Priority::Low),
+            "⏬" => Some(Priority::Lowest),
+            _ if marker.len() == 3 && marker.starts_with('(') && marker.ends_with(')') => {
+                Some(Priority::Lowest)
+            }
+            _ => None,
+        }
+    }
+
+    /// The marker written into new lines. Only the emojis cover all five levels.
+    pub fn marker(&self) -> &'static str {
+        match self {
+            Priority::Highest => "🔺",
+            Priority::High => "⏫",
+            Priority::Medium => "🔼",
+            Priority::Low => "🔽",
+            Priority::Lowest => "⏬",
+        }
+    }
+
+    /// For comparing tasks with and without a priority:
+    /// like Obsidian Tasks, none counts as between medium and low.
+    pub fn rank(priority: Option<Priority>) -> u8 {
+        match priority {
+            Some(Priority::Highest) => 5,
+            Some(Priority::High) => 4,
+            Some(Priority::Medium) => 3,
+            None => 2,
+            Some(Priority::Low) => 1,
+            Some(Priority::Lowest) => 0,
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Priority::Highest => "highest",
+            Priority::High => "high",
+            Priority::Medium => "medium",
+            Priority::Low => "low",
+            Priority::Lowest => "lowest",
+        })
+    }
+}
+
+/// Accepts the names as well as any marker, e.g. `high`, `!!`, `B` or `⏫`.
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Priority, String> {
+        let letter = format!("({})", s.to_uppercase());
+        match s.to_lowercase().as_str() {
+            "highest" => Ok(Priority::Highest),
+            "high" => Ok(Priority::High),
+            "medium" => Ok(Priority::Medium),
+            "low" => Ok(Priority::Low),
+            "lowest" => Ok(Priority::Lowest),
+            _ if s.len() == 1 && s.chars().all(|c| c.is_ascii_alphabetic()) => {
+                Ok(Priority::from_marker(&letter).unwrap())
+            }
+            _ => Priority::from_marker(s)
+                .ok_or_else(|| "expected one of: highest, high, medium, low, lowest".to_string()),
+        }
+    }
+}
@@ -0,0 +1,505 @@
+//! Filtering tasks with expressions like `+work @alice due:<2024-07-10 -done`.
+
+use crate::dates::{parse_date, DateKind};
+use crate::error::TodoError;
+use crate::item::TodoItem;
+use crate::priority::Priority;
+use chrono::NaiveDate;
+
+/// A parsed filter expression.
+/// Terms next to each other have to match all, `or`, `not`/`-` and parentheses work as usual.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// matches every task, the empty query
+    Any,
+    /// both terms, written next to each other or with `and`
+    And(Box<Query>, Box<Query>),
+    /// `a or b`
+    Or(Box<Query>, Box<Query>),
+    /// `not a` or `-a`
+    Not(Box<Query>),
+    /// `+work` or `#work`
+    Tag(String),
+    /// `@alice`
+    Name(String),
+    /// `due:<2024-07-10`, `due:today`, `due:none`, and the same for
+    /// `scheduled:`, `start:`, `created:` and `done:`
+    Date(DateKind, DueFilter),
+    /// `priority:high`, `prio:>=medium`, `priority:none`, ...
+    Priority(PriorityFilter),
+    /// `done`
+    Done,
+    /// `open`
+    Open,
+    /// `section:Work`
+    Section(String),
+    /// anything else, searched for in the title
+    Text(String),
+}
+
+/// Which dates a `Query::Date` matches.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DueFilter {
+    /// `due:none`
+    None,
+    /// `due:any`
+    Any,
+    /// `due:2024-07-10` or `due:=2024-07-10`
+    On(NaiveDate),
+    /// `due:<2024-07-10`
+    Before(NaiveDate),
+    /// `due:<=2024-07-10`
+    OnOrBefore(NaiveDate),
+    /// `due:>2024-07-10`
+    After(NaiveDate),
+    /// `due:>=2024-07-10`
+    OnOrAfter(NaiveDate),
+}
+impl DueFilter {
+    fn matches(&self, date: Option<NaiveDate>) -> bool {
+        match (self, date) {
+            (DueFilter::None, date) => date.is_none(),
+            (_, None) => false,
+            (DueFilter::Any, Some(_)) => true,
+            (DueFilter::On(day), Some(date)) => date == *day,
+            (DueFilter::Before(day), Some(date)) => date < *day,
+            (DueFilter::OnOrBefore(day), Some(date)) => date <= *day,
+            (DueFilter::After(day), Some(date)) => date > *day,
+            (DueFilter::OnOrAfter(day), Some(date)) => date >= *day,
+        }
+    }
+}
+
+/// Tasks without a priority count as between medium and low, like when sorting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriorityFilter {
+    /// `priority:none`
+    None,
+    /// `priority:any`
+    Any,
+    /// `priority:high` or `priority:=high`
+    Is(Priority),
+    /// `priority:<high`
+    Below(Priority),
+    /// `priority:<=high`
+    AtMost(Priority),
+    /// `priority:>medium`
+    Above(Priority),
+    /// `priority:>=medium`
+    AtLeast(Priority),
+}
+impl PriorityFilter {
+    fn matches(&self, priority: Option<Priority>) -> bool {
+        let rank = Priority::rank(priority);
+        match self {
+            PriorityFilter::None => priority.is_none(),
+            PriorityFilter::Any => priority.is_some(),
+            PriorityFilter::Is(level) => priority == Some(*level),
+            PriorityFilter::Below(level) => rank < Priority::rank(Some(*level)),
+            PriorityFilter::AtMost(level) => rank <= Priority::rank(Some(*level)),
+            PriorityFilter::Above(level) => rank > Priority::rank(Some(*level)),
+            PriorityFilter::AtLeast(level) => rank >= Priority::rank(Some(*level)),
+        }
+    }
+}
+
+impl Query {
+    /// Parses `input`, resolving relative dates like `today` against `today`.
+    pub fn parse(input: &str, today: NaiveDate) -> Result<Query, TodoError> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Ok(Query::Any);
+        }
+
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+            today,
+        };
+        let query = parser.or()?;
+        // `or` only stops early at a `)` that was never opened
+        if parser.pos < tokens.len() {
+            return Err(TodoError::InvalidQuery("unmatched `)`".to_string()));
+        }
+        Ok(query)
+    }
+
+    /// Parses command line arguments as one query, so `todo list +work or +home`
+    /// and `todo list "+work or +home"` are the same.
+    pub fn from_args(args: &[String], today: NaiveDate) -> Result<Query, TodoError> {
+        Query::parse(&args.join(" "), today)
+    }
+
+    /// Both `self` and `other` have to match.
+    pub fn and(self, other: Query) -> Query {
+        match (self, other) {
+            (Query::Any, query) | (query, Query::Any) => query,
+            (left, right) => Query::And(Box::new(left), Box::new(right)),
+        }
+    }
+
+    /// Checks whether `item` fulfills the query.
+    pub fn matches(&self, item: &TodoItem) -> bool {
+        match self {
+            Query::Any => true,
+            Query::And(left, right) => left.matches(item) && right.matches(item),
+            Query::Or(left, right) => left.matches(item) || right.matches(item),
+            Query::Not(query) => !query.matches(item),
+            Query::Tag(tag) => item.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)),
+            Query::Name(name) => item.names.iter().any(|n| n.eq_ignore_ascii_case(name)),
+            Query::Date(kind, filter) => filter.matches(item.date(*kind)),
+            Query::Priority(filter) => filter.matches(item.priority),
+            Query::Done => item.is_completed,
+            Query::Open => !item.is_completed,
+            Query::Section(name) => item.in_section(name),
+            Query::Text(text) => item.title.to_lowercase().contains(&text.to_lowercase()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Not,
+    And,
+    Or,
+    Word(String),
+    Quoted(String), // always text, even if it looks like a filter
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, TodoError> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => (),
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '"' | '\'' => tokens.push(Token::Quoted(read_quoted(&mut chars, c)?)),
+            // `-term` negates, a lone `-` is just text
+            '-' if chars.peek().is_some_and(|next| !next.is_whitespace()) => {
+                tokens.push(Token::Not)
+            }
+            c => {
+                let mut word = String::from(c);
+                while let Some(next) = chars.peek().copied() {
+                    if next.is_whitespace() || next == '(' || next == ')' {
+                        break;
+                    }
+                    chars.next();
+                    if next == '"' || next == '\'' {
+                        // `section:"Big Project"`
+                        word.push_str(&read_quoted(&mut chars, next)?);
+                    } else {
+                        word.push(next);
+                    }
+                }
+                tokens.push(match word.to_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads up to the closing `quote`, which has already been opened.
+fn read_quoted(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    quote: char,
+) -> Result<String, TodoError> {
+    let mut text = String::new();
+    for c in chars.by_ref() {
+        if c == quote {
+            return Ok(text);
+        }
+        text.push(c);
+    }
+    Err(TodoError::InvalidQuery(format!("missing closing {quote}")))
+}
+
+fn describe(token: Option<&Token>) -> String {
+    match token {
+        None => "end of the query".to_string(),
+        Some(Token::Open) => "`(`".to_string(),
+        Some(Token::Close) => "`)`".to_string(),
+        Some(Token::Not) => "`not`".to_string(),
+        Some(Token::And) => "`and`".to_string(),
+        Some(Token::Or) => "`or`".to_string(),
+        Some(Token::Word(word)) | Some(Token::Quoted(word)) => format!("`{word}`"),
+    }
+}
+
+/// Recursive descent over the tokens: `or` binds weaker than `and`, which binds weaker than `not`.
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    today: NaiveDate,
+}
+impl Parser<'_> {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Query, TodoError> {
+        let mut query = self.and()?;
+        while self.tokens.get(self.pos) == Some(&Token::Or) {
+            self.pos += 1;
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<Query, TodoError> {
+        let mut query = self.not()?;
+        loop {
+            match self.tokens.get(self.pos) {
+                None | Some(Token::Close) | Some(Token::Or) => return Ok(query),
+                Some(Token::And) => self.pos += 1,
+                // terms without an operator in between have to match all
+                Some(_) => (),
+            }
+            query = Query::And(Box::new(query), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> Result<Query, TodoError> {
+        let today = self.today;
+        match self.next().cloned() {
+            Some(Token::Not) => Ok(Query::Not(Box::new(self.not()?))),
+            Some(Token::Open) => {
+                let query = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(query),
+                    _ => Err(TodoError::InvalidQuery("missing `)`".to_string())),
+                }
+            }
+            Some(Token::Word(word)) => parse_term(&word, today),
+            Some(Token::Quoted(text)) => Ok(Query::Text(text)),
+            token => Err(TodoError::InvalidQuery(format!(
+                "expected a filter but found {}",
+                describe(token.as_ref())
+            ))),
+        }
+    }
+}
+
+fn parse_term(word: &str, today: NaiveDate) -> Result<Query, TodoError> {
+    if let Some(tag) = word.strip_prefix(['+', '#']).filter(|tag| !tag.is_empty()) {
+        return Ok(Query::Tag(format!("#{tag}")));
+    }
+    if word.len() > 1 && word.starts_with('@') {
+        return Ok(Query::Name(word.to_string()));
+    }
+
+    match word.to_lowercase().as_str() {
+        "done" => return Ok(Query::Done),
+        "open" => return Ok(Query::Open),
+        "overdue" => {
+            return Ok(Query::Open.and(Query::Date(DateKind::Due, DueFilter::Before(today))))
+        }
+        _ => (),
+    }
+
+    let date_filter = word
+        .split_once(':')
+        .and_then(|(key, value)| date_kind(key).map(|kind| (kind, value)));
+    if let Some((kind, value)) = date_filter {
+        return Ok(Query::Date(kind, parse_due_filter(value, today)?));
+    }
+
+    match word.split_once(':') {
+        Some((key, value))
+            if key.eq_ignore_ascii_case("priority") || key.eq_ignore_ascii_case("prio") =>
+        {
+            Ok(Query::Priority(parse_priority(value)?))
+        }
+        Some((key, value)) if key.eq_ignore_ascii_case("section") => {
+            Ok(Query::Section(value.to_string()))
+        }
+        _ => Ok(Query::Text(word.to_string())),
+    }
+}
+
+fn date_kind(key: &str) -> Option<DateKind> {
+    match key.to_lowercase().as_str() {
+        "due" => Some(DateKind::Due),
+        "scheduled" => Some(DateKind::Scheduled),
+        "start" => Some(DateKind::Start),
+        "created" => Some(DateKind::Created),
+        "done" => Some(DateKind::Done),
+        _ => None,
+    }
+}
+
+type DueFilterFn = fn(NaiveDate) -> DueFilter;
+
+fn parse_due_filter(value: &str, today: NaiveDate) -> Result<DueFilter, TodoError> {
+    match value.to_lowercase().as_str() {
+        "none" => return Ok(DueFilter::None),
+        "any" => return Ok(DueFilter::Any),
+        _ => (),
+    }
+
+    // the longer operators first, so `<=` isn't taken for `<`
+    let operators: [(&str, DueFilterFn); 5] = [
+        ("<=", DueFilter::OnOrBefore),
+        (">=", DueFilter::OnOrAfter),
+        ("<", DueFilter::Before),
+        (">", DueFilter::After),
+        ("=", DueFilter::On),
+    ];
+    let (filter, date): (DueFilterFn, &str) = operators
+        .iter()
+        .find_map(|(op, filter)| value.strip_prefix(op).map(|date| (*filter, date)))
+        .unwrap_or((DueFilter::On, value));
+    Ok(filter(parse_date(date, today)?))
+}
+
+type PriorityFilterFn = fn(Priority) -> PriorityFilter;
+
+fn parse_priority(value: &str) -> Result<PriorityFilter, TodoError> {
+    match value.to_lowercase().as_str() {
+        "none" => return Ok(PriorityFilter::None),
+        "any" => return Ok(PriorityFilter::Any),
+        _ => (),
+    }
+
+    let operators: [(&str, PriorityFilterFn); 5] = [
+        ("<=", PriorityFilter::AtMost),
+        (">=", PriorityFilter::AtLeast),
+        ("<", PriorityFilter::Below),
+        (">", PriorityFilter::Above),
+        ("=", PriorityFilter::Is),
+    ];
+    let (filter, level): (PriorityFilterFn, &str) = operators
+        .iter()
+        .find_map(|(op, filter)| value.strip_prefix(op).map(|level| (*filter, level)))
+        .unwrap_or((PriorityFilter::Is, value));
+    let level: Priority = level
+        .parse()
+        .map_err(|e| TodoError::InvalidQuery(format!("\"{level}\" is not a priority, {e}")))?;
+    Ok(filter(level))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ConfigFile, TodoConfig};
+    use crate::item::parse_line;
+
+    fn today() -> NaiveDate {
+        "2024-07-03".parse().unwrap()
+    }
+
+    fn matching(query: &str, lines: &[&str]) -> Vec<String> {
+        let conf_todo = TodoConfig::new(&ConfigFile::default());
+        let query = Query::parse(query, today()).unwrap();
+        lines
+            .iter()
+            .filter_map(|line| parse_line(line, &conf_todo))
+            .filter(|item| query.matches(item))
+            .map(|item| item.title)
+            .collect()
+    }
+
+    const TASKS: [&str; 5] = [
+        "- [ ] invoice 2024-07-01 #work @alice",
+        "- [ ] slides 2024-07-10 #work ⏫",
+        "- [x] groceries #home",
+        "- [ ] call mom #home 🔽",
+        "- [ ] fix bike",
+    ];
+
+    #[test]
+    fn precedence() {
+        let tag = |name: &str| Query::Tag(format!("#{name}"));
+        assert_eq!(
+            Query::parse("+a +b or not +c", today()).unwrap(),
+            Query::Or(
+                Box::new(Query::And(Box::new(tag("a")), Box::new(tag("b")))),
+                Box::new(Query::Not(Box::new(tag("c")))),
+            )
+        );
+        assert_eq!(
+            Query::parse("+a and (+b or -+c)", today()).unwrap(),
+            Query::And(
+                Box::new(tag("a")),
+                Box::new(Query::Or(
+                    Box::new(tag("b")),
+                    Box::new(Query::Not(Box::new(tag("c")))),
+                )),
+            )
+        );
+        assert_eq!(Query::parse("  ", today()).unwrap(), Query::Any);
+    }
+
+    #[test]
+    fn terms() {
+        assert_eq!(matching("+work @alice", &TASKS), vec!["invoice"]);
+        assert_eq!(matching("#home -done", &TASKS), vec!["call mom"]);
+        assert_eq!(matching("done", &TASKS), vec!["groceries"]);
+        assert_eq!(matching("BIKE", &TASKS), vec!["fix bike"]);
+        assert_eq!(matching("\"call mom\"", &TASKS), vec!["call mom"]);
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(matching("overdue", &TASKS), vec!["invoice"]);
+        assert_eq!(matching("due:>today", &TASKS), vec!["slides"]);
+        assert_eq!(
+            matching("due:<=2024-07-10 +work", &TASKS),
+            vec!["invoice", "slides"]
+        );
+        assert_eq!(
+            matching("due:none +home", &TASKS),
+            vec!["groceries", "call mom"]
+        );
+        assert_eq!(
+            matching("due:any or @alice", &TASKS),
+            vec!["invoice", "slides"]
+        );
+    }
+
+    #[test]
+    fn priorities() {
+        assert_eq!(matching("priority:high", &TASKS), vec!["slides"]);
+        // tasks without a priority rank between medium and low
+        assert_eq!(
+            matching("prio:<medium -done", &TASKS),
+            vec!["invoice", "call mom", "fix bike"]
+        );
+        assert_eq!(
+            matching("priority:>=low priority:any", &TASKS),
+            vec!["slides", "call mom"]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        for input in [
+            "(+a",
+            "+a)",
+            "+a or",
+            "not",
+            "\"open",
+            "due:someday",
+            "priority:urgent",
+        ] {
+            assert!(
+                matches!(
+                    Query::parse(input, today()),
+                    Err(TodoError::InvalidQuery(_) | TodoError::InvalidDate(_))
+                ),
+                "{input}"
+            );
+        }
+    }
+}
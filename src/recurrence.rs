@@ -0,0 +1,421 @@
+//! Tasks that come back, like `every week` or `every month on the 1st`.
+
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How many intervals `Recurrence::next` looks ahead for a month that has the day of the rule.
+/// Enough even for a yearly 5th Monday in February, which only exists every 28 years.
+const MAX_SKIPPED_MONTHS: u32 = 100;
+
+/// How often a task recurs, see `Recurrence::parse` for the notations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Recurrence {
+    /// How many units lie between two occurrences, `2` in `every 2 weeks`.
+    pub interval: u32,
+    /// What `interval` counts.
+    pub unit: RecurrenceUnit,
+    /// The day within the week or month, if the rule names one.
+    pub on: Option<RecurrenceDay>,
+    /// Count from the day it was completed instead of its dates.
+    pub when_done: bool,
+}
+
+/// What the interval of a `Recurrence` counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RecurrenceUnit {
+    /// `every day`, `rec:1d`
+    Day,
+    /// `every week`, `rec:1w`
+    Week,
+    /// `every month`, `rec:1m`
+    Month,
+    /// `every year`, `rec:1y`
+    Year,
+}
+
+/// Which day of the week or month it recurs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RecurrenceDay {
+    /// `every week on monday`, `every monday`
+    Weekday(Weekday),
+    /// `every month on the 15th`, the last day for shorter months
+    DayOfMonth(u32),
+    /// `every month on the last`
+    LastDay,
+    /// `every month on the 2nd monday`
+    NthWeekday(u32, Weekday),
+    /// `every month on the last friday`
+    LastWeekday(Weekday),
+}
+
+impl Recurrence {
+    /// Reads a rule like
+    /// - `every day`, `every 3 days`, `every other week`, `every month`, `every year`
+    /// - `every monday`, `every 2nd monday` (every other Monday), `every 2 weeks on friday`
+    /// - `every month on the 1st`, `every month on the last`, `every month on the 2nd tuesday`
+    /// - any of them followed by `when done`, to count from the day it was completed
+    /// - todo.txt's `rec:1w`, from the day it was completed, or `rec:+1w`, from its due date
+    pub fn parse(input: &str) -> Option<Recurrence> {
+        let input = input.to_lowercase();
+        if let Some(rule) = input.strip_prefix("rec:") {
+            return Recurrence::parse_todotxt(rule);
+        }
+
+        let mut words: Vec<&str> = input.split_whitespace().collect();
+        if words.first() != Some(&"every") {
+            return None;
+        }
+        let when_done = words.ends_with(&["when", "done"]);
+        if when_done {
+            words.truncate(words.len() - 2);
+        }
+
+        let (interval, rest) = match words.get(1).and_then(|word| parse_count(word)) {
+            Some(interval) => (interval, &words[2..]),
+            None => (1, &words[1..]),
+        };
+        let (unit, on, rest) = match rest {
+            [weekday, rest @ ..] if weekday.parse::<Weekday>().is_ok() => (
+                RecurrenceUnit::Week,
+                Some(RecurrenceDay::Weekday(weekday.parse().ok()?)),
+                rest,
+            ),
+            [unit, rest @ ..] => (parse_unit(unit, interval)?, None, rest),
+            [] => return None,
+        };
+        let on = match (unit, rest) {
+            (_, []) => on,
+            (RecurrenceUnit::Week, ["on", weekday]) if on.is_none() => {
+                Some(RecurrenceDay::Weekday(weekday.parse().ok()?))
+            }
+            (RecurrenceUnit::Month, ["on", "the", day @ ..]) => Some(parse_day_of_month(day)?),
+            _ => return None,
+        };
+
+        Some(Recurrence {
+            interval,
+            unit,
+            on,
+            when_done,
+        })
+    }
+
+    /// `1d`, `2w`, `3m` or `1y` after `rec:`, a leading `+` counts from the due date.
+    fn parse_todotxt(rule: &str) -> Option<Recurrence> {
+        let (strict, rule) = match rule.strip_prefix('+') {
+            Some(rule) => (true, rule),
+            None => (false, rule),
+        };
+        let split = rule.find(|c: char| !c.is_ascii_digit())?;
+        let interval: u32 = rule[..split].parse().ok().filter(|n| *n > 0)?;
+        let unit = match &rule[split..] {
+            "d" => RecurrenceUnit::Day,
+            "w" => RecurrenceUnit::Week,
+            "m" => RecurrenceUnit::Month,
+            "y" => RecurrenceUnit::Year,
+            _ => return None,
+        };
+        Some(Recurrence {
+            interval,
+            unit,
+            on: None,
+            when_done: !strict,
+        })
+    }
+
+    /// The first day after `from` the task recurs on.
+    pub fn next(&self, from: NaiveDate) -> Option<NaiveDate> {
+        match (self.unit, self.on) {
+            (RecurrenceUnit::Day, _) => from.checked_add_days(Days::new(self.interval.into())),
+            (RecurrenceUnit::Week, Some(RecurrenceDay::Weekday(weekday))) => {
+                let days_ahead = (weekday.num_days_from_monday() + 7
+                    - from.weekday().num_days_from_monday())
+                    % 7;
+                let next = match days_ahead {
+                    0 => from.checked_add_days(Days::new(7))?,
+                    days => from.checked_add_days(Days::new(days.into()))?,
+                };
+                next.checked_add_days(Days::new(7 * u64::from(self.interval - 1)))
+            }
+            (RecurrenceUnit::Week, _) => {
+                from.checked_add_days(Days::new(7 * u64::from(self.interval)))
+            }
+            (RecurrenceUnit::Month, Some(day)) => {
+                // the month `from` is in counts if the day is still ahead, for `every month on the 1st` on the 15th
+                let this_month = day.in_month(from.year(), from.month());
+                if let Some(date) = this_month.filter(|date| *date > from && self.interval == 1) {
+                    return Some(date);
+                }
+                // months without the day are skipped, not every month has a 5th Monday
+                let first = from.with_day(1)?;
+                (1..=MAX_SKIPPED_MONTHS).find_map(|step| {
+                    let months = self.interval.checked_mul(step)?;
+                    let month = first.checked_add_months(Months::new(months))?;
+                    day.in_month(month.year(), month.month())
+                })
+            }
+            (RecurrenceUnit::Month, None) => from.checked_add_months(Months::new(self.interval)),
+            (RecurrenceUnit::Year, _) => {
+                from.checked_add_months(Months::new(self.interval.checked_mul(12)?))
+            }
+        }
+    }
+}
+
+impl RecurrenceDay {
+    /// The day in the given month, if there is one (there's not always a 5th Monday).
+    fn in_month(&self, year: i32, month: u32) -> Option<NaiveDate> {
+        let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+        let last = first.checked_add_months(Months::new(1))?.pred_opt()?;
+        match *self {
+            RecurrenceDay::Weekday(_) => None,
+            RecurrenceDay::DayOfMonth(day) => first.with_day(day.min(last.day())),
+            RecurrenceDay::LastDay => Some(last),
+            RecurrenceDay::NthWeekday(n, weekday) => {
+                NaiveDate::from_weekday_of_month_opt(year, month, weekday, n.try_into().ok()?)
+            }
+            RecurrenceDay::LastWeekday(weekday) => {
+                let days_back = (last.weekday().num_days_from_monday() + 7
+                    - weekday.num_days_from_monday())
+                    % 7;
+                last.checked_sub_days(Days::new(days_back.into()))
+            }
+        }
+    }
+}
+
+/// `2`, `2nd`, `second` or `other`.
+fn parse_count(word: &str) -> Option<u32> {
+    let number = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    if !number.is_empty() {
+        let suffix = &word[number.len()..];
+        return number
+            .parse()
+            .ok()
+            .filter(|n| *n > 0 && ["", "st", "nd", "rd", "th"].contains(&suffix));
+    }
+    match word {
+        "other" | "second" => Some(2),
+        "first" => Some(1),
+        "third" => Some(3),
+        "fourth" => Some(4),
+        "fifth" => Some(5),
+        _ => None,
+    }
+}
+
+fn parse_unit(word: &str, interval: u32) -> Option<RecurrenceUnit> {
+    // `every 2 week` is fine, `every day` has to be singular
+    let word = if interval > 1 {
+        word.strip_suffix('s').unwrap_or(word)
+    } else {
+        word
+    };
+    match word {
+        "day" => Some(RecurrenceUnit::Day),
+        "week" => Some(RecurrenceUnit::Week),
+        "month" => Some(RecurrenceUnit::Month),
+        "year" => Some(RecurrenceUnit::Year),
+        _ => None,
+    }
+}
+
+/// What follows `on the`: `1st`, `15th`, `last`, `last day`, `2nd monday` or `last friday`.
+fn parse_day_of_month(words: &[&str]) -> Option<RecurrenceDay> {
+    match words {
+        ["last"] | ["last", "day"] => Some(RecurrenceDay::LastDay),
+        ["last", weekday] => Some(RecurrenceDay::LastWeekday(weekday.parse().ok()?)),
+        [day] => Some(RecurrenceDay::DayOfMonth(
+            parse_count(day).filter(|day| *day <= 31)?,
+        )),
+        [n, weekday] => Some(RecurrenceDay::NthWeekday(
+            parse_count(n).filter(|n| *n <= 5)?,
+            weekday.parse().ok()?,
+        )),
+        _ => None,
+    }
+}
+
+/// Written so that `parse` reads it again, e.g. `every 2 weeks on monday`.
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = match self.unit {
+            RecurrenceUnit::Day => "day",
+            RecurrenceUnit::Week => "week",
+            RecurrenceUnit::Month => "month",
+            RecurrenceUnit::Year => "year",
+        };
+        match self.interval {
+            1 => write!(f, "every {unit}")?,
+            n => write!(f, "every {n} {unit}s")?,
+        }
+        match self.on {
+            None => (),
+            Some(RecurrenceDay::Weekday(weekday)) => write!(f, " on {}", weekday_name(weekday))?,
+            Some(RecurrenceDay::DayOfMonth(day)) => write!(f, " on the {}", ordinal(day))?,
+            Some(RecurrenceDay::LastDay) => write!(f, " on the last")?,
+            Some(RecurrenceDay::NthWeekday(n, weekday)) => {
+                write!(f, " on the {} {}", ordinal(n), weekday_name(weekday))?
+            }
+            Some(RecurrenceDay::LastWeekday(weekday)) => {
+                write!(f, " on the last {}", weekday_name(weekday))?
+            }
+        }
+        if self.when_done {
+            write!(f, " when done")?;
+        }
+        Ok(())
+    }
+}
+
+fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "monday",
+        Weekday::Tue => "tuesday",
+        Weekday::Wed => "wednesday",
+        Weekday::Thu => "thursday",
+        Weekday::Fri => "friday",
+        Weekday::Sat => "saturday",
+        Weekday::Sun => "sunday",
+    }
+}
+
+fn ordinal(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{n}{suffix}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(input: &str) -> NaiveDate {
+        input.parse().unwrap()
+    }
+
+    fn next(rule: &str, from: &str) -> Option<NaiveDate> {
+        Recurrence::parse(rule).unwrap().next(date(from))
+    }
+
+    #[test]
+    fn notations() {
+        for (input, written) in [
+            ("every day", "every day"),
+            ("Every 3 days", "every 3 days"),
+            ("every other week", "every 2 weeks"),
+            ("every monday", "every week on monday"),
+            ("every 2nd monday", "every 2 weeks on monday"),
+            ("every 2 weeks on friday", "every 2 weeks on friday"),
+            ("every month on the 1st", "every month on the 1st"),
+            ("every month on the last day", "every month on the last"),
+            (
+                "every month on the second tuesday",
+                "every month on the 2nd tuesday",
+            ),
+            (
+                "every month on the last friday",
+                "every month on the last friday",
+            ),
+            ("every year when done", "every year when done"),
+            ("rec:1w", "every week when done"),
+            ("rec:+2m", "every 2 months"),
+        ] {
+            let rule = Recurrence::parse(input).unwrap();
+            assert_eq!(rule.to_string(), written, "{input}");
+            assert_eq!(Recurrence::parse(written), Some(rule), "{written}");
+        }
+    }
+
+    #[test]
+    fn not_a_rule() {
+        for input in [
+            "every",
+            "everyday",
+            "every 0 days",
+            "every days",
+            "every week on",
+            "every month on the 32nd",
+            "every month on the 6th monday",
+            "every monday on friday",
+            "rec:1x",
+            "rec:0d",
+        ] {
+            assert_eq!(Recurrence::parse(input), None, "{input}");
+        }
+    }
+
+    #[test]
+    fn next_dates() {
+        // 2024-07-03 is a Wednesday
+        assert_eq!(next("every day", "2024-07-03"), Some(date("2024-07-04")));
+        assert_eq!(
+            next("every 2 weeks", "2024-07-03"),
+            Some(date("2024-07-17"))
+        );
+        assert_eq!(next("every friday", "2024-07-03"), Some(date("2024-07-05")));
+        assert_eq!(
+            next("every wednesday", "2024-07-03"),
+            Some(date("2024-07-10"))
+        );
+        assert_eq!(
+            next("every 2nd monday", "2024-07-03"),
+            Some(date("2024-07-15"))
+        );
+        assert_eq!(next("every month", "2024-01-31"), Some(date("2024-02-29")));
+        assert_eq!(next("every year", "2024-02-29"), Some(date("2025-02-28")));
+        // too far ahead to compute
+        assert_eq!(next("rec:999999999y", "2024-07-01"), None);
+        assert_eq!(next("rec:999999999m", "2024-07-01"), None);
+    }
+
+    #[test]
+    fn next_day_of_month() {
+        // still ahead this month
+        assert_eq!(
+            next("every month on the 20th", "2024-07-15"),
+            Some(date("2024-07-20"))
+        );
+        assert_eq!(
+            next("every month on the 1st", "2024-07-15"),
+            Some(date("2024-08-01"))
+        );
+        // shorter months get their last day
+        assert_eq!(
+            next("every month on the 31st", "2024-01-31"),
+            Some(date("2024-02-29"))
+        );
+        assert_eq!(
+            next("every month on the last", "2024-02-29"),
+            Some(date("2024-03-31"))
+        );
+        assert_eq!(
+            next("every month on the last friday", "2024-07-26"),
+            Some(date("2024-08-30"))
+        );
+        assert_eq!(
+            next("every 2 months on the 2nd tuesday", "2024-07-09"),
+            Some(date("2024-09-10"))
+        );
+    }
+
+    #[test]
+    fn months_without_the_day_are_skipped() {
+        // August 2024 has no 5th Monday, September has
+        assert_eq!(
+            next("every month on the 5th monday", "2024-07-29"),
+            Some(date("2024-09-30"))
+        );
+        // February 2016 had five Mondays, the next February with five is in 2044
+        assert_eq!(
+            next("every 12 months on the 5th monday", "2016-02-29"),
+            Some(date("2044-02-29"))
+        );
+    }
+}
@@ -1,518 +1,582 @@
-use chrono::prelude::*;
+use crate::output::{OutputFormat, TaskJson};
+use chrono::{Local, NaiveDateTime};
 use colored::{ColoredString, Colorize};
-use dirs;
-use regex::Regex;
-use serde::{Deserialize, Serialize};
-use std::fs;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{self, Write};
-use std::path::PathBuf;
-use toml;
-
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct TodoPath {
-    pub todo_path: PathBuf,
-    pub todo_filename: PathBuf,
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use todo_md::*;
+
+/// Decides how questions are answered: by the user, automatically or not at all.
+pub struct Prompt {
+    assume_yes: bool,
+    interactive: bool,
+    to_stderr: bool, // stdout is reserved for JSON, questions and notices go to stderr
 }
-
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct TodoFormatting {
-    pub checkbox_style: String, // accepted: "md", "logseq"
-}
-
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct ConfigFile {
-    pub path: TodoPath,
-    pub format: TodoFormatting,
-}
-impl ConfigFile {
-    /// Check config. If config is invalid, either use defaults or let user configure manually.
-    pub fn init() -> ConfigFile {
-        // setting some defaults
-        let mut conf = ConfigFile {
-            format: TodoFormatting {
-                checkbox_style: "md".to_string(),
-            },
-            path: TodoPath {
-                todo_path: dirs::home_dir().unwrap(),
-                todo_filename: "todo.md".into(),
-            },
-        };
-        let path_to_conf = dirs::config_dir()
-            .expect("config dir error")
-            .join(PathBuf::from("todo-md-rs"))
-            .join(PathBuf::from("config.toml"));
-
-        if check_dir_exists(&path_to_conf) {
-            let contents: String = fs::read_to_string(&path_to_conf).unwrap();
-            conf = match toml::from_str(&contents) {
-                Ok(content) => content,
-                Err(_e) => {
-                    println!("error in your config!");
-                    println!("using the following defaults: {conf:#?}");
-                    conf
-                }
-            };
-        } else {
-            match readinput("create base configuraton file? ( [y]es / [n]o ): ")
-                .expect("input failed")
-                .as_str()
-            {
-                "y" | "yes" => {
-                    create_path(&path_to_conf);
-                    let _ = export_line(&path_to_conf, toml::to_string(&conf).unwrap(), false);
-                }
-                _ => {
-                    println!("using temporary path");
-                    let toml = toml::to_string(&conf).unwrap();
-                    println!("{toml:#?}");
-                }
-            }
+impl Prompt {
+    /// `no_input` is implied when stdin is not a terminal, so scripts fail instead of hanging.
+    pub fn new(assume_yes: bool, no_input: bool, output: OutputFormat) -> Prompt {
+        Prompt {
+            assume_yes,
+            interactive: !no_input && io::stdin().is_terminal(),
+            to_stderr: output != OutputFormat::Text,
         }
+    }
 
-        // onto checking for the todo.md file itself
-
-        let mut unified_path: PathBuf = conf.path.todo_path.join(&conf.path.todo_filename);
-
-        while !check_dir_exists(&unified_path) {
-            match readinput(&format!(
-                "create {} ?\n( [y]es / [n]o / [c]hange ): ",
-                unified_path.display()
-            ))
-            .expect("input failed")
-            .as_str()
-            {
-                "y" | "yes" => create_path(&unified_path),
-                "c" | "change" => {
-                    println!("so you want to change?");
-                    let new_path = PathBuf::from(
-                        readinput("desired path to todo.md file (including filename): ").unwrap(),
-                    );
-                    conf.path.todo_filename = new_path.into_iter().last().unwrap().into();
-                    conf.path.todo_path = new_path.parent().unwrap().into();
-                    unified_path = conf.path.todo_path.join(conf.path.todo_filename.clone());
-                    // create the todo.md file
-                    create_path(&unified_path);
-                    // update the config file
-                    let _ = export_line(&path_to_conf, toml::to_string(&conf).unwrap(), false);
-                }
-                _ => panic!("user refused"),
-            }
+    /// Returns "y" right away with `--yes`, otherwise asks if possible.
+    pub fn ask(&self, question: &str) -> Result<String, TodoError> {
+        if self.assume_yes {
+            Ok("y".to_string())
+        } else if self.interactive {
+            self.read(question)
+        } else {
+            Err(TodoError::InputRequired(question.to_string()))
         }
-        return conf;
     }
-}
 
-// creates file and parent path
-pub fn create_path(path: &PathBuf) {
-    match check_dir_exists(&path) {
-        false => {
-            if let Some(parent) = path.parent() {
-                let _ = std::fs::create_dir_all(parent);
-            }
-            println!("creating: {path:?}");
-            let _ = File::create(path.clone()).expect("creating file failed");
-        }
-        true => {
-            println!("file already exists");
+    /// Tells the user something that isn't part of the output.
+    pub fn notice(&self, message: &str) {
+        if self.to_stderr {
+            eprintln!("{message}");
+        } else {
+            println!("{message}");
         }
     }
-}
 
-/// Parses a Vec of raw Strings and writes them into the Todo container.
-/// A task will only be recognised by the configured `TodoConfig.completion_style`
-pub fn strings_to_todo(lines: Vec<String>, conf_todo: &TodoConfig) -> Vec<TodoItem> {
-    let mut item_list: Vec<TodoItem> = Vec::new();
-
-    for (linecount, line) in lines.iter().enumerate() {
-        // - task detected by completion pattern -
-        // for now, the order is so that the date (and maybe at some point tags) will be removed before
-        // feeding the rest of the line into the string
-        if conf_todo.completion_style.is_match(&line) {
-            let mut item = TodoItem::new();
-            let mut l_mut: String = line.to_string();
-
-            // ID and LINE, duh
-            item.id = item_list.len() + 1;
-            item.line = linecount + 1;
-
-            // COMPLETION
-            item.is_completed = if conf_todo.completion_done.is_match(&line) {
-                l_mut = conf_todo.completion_done.replace(&l_mut, "").into(); // remove the checkbox
-                true
-            } else {
-                l_mut = conf_todo.completion_style.replace(&l_mut, "").into(); // remove the checkbox
-                false
-            };
-
-            // DATE
-            item.date_due = match conf_todo.date_format.captures(&line) {
-                Some(date) => {
-                    l_mut = conf_todo.date_format.replace(&l_mut, " ").into(); // remove the first date and assume it's the due_date
-                    Some(date[0].parse::<NaiveDate>().unwrap())
-                }
-                _ => None,
-            };
-
-            // TAG
-            let tag_re = Regex::new(r"#\w+").unwrap();
-            tag_re
-                .captures_iter(&line)
-                .map(|captures| captures.get(0).unwrap().as_str())
-                .for_each(|tag| item.tags.push(tag.into()));
-            l_mut = tag_re.replace_all(&l_mut, "").into();
-
-            // NAME
-            let name_re = Regex::new(r"@\w+").unwrap();
-            name_re
-                .captures_iter(&line)
-                .map(|captures| captures.get(0).unwrap().as_str())
-                .for_each(|name| item.names.push(name.into()));
-            l_mut = name_re.replace_all(&l_mut, "").into();
-
-            // TITLE
-            item.title = l_mut.trim().to_string(); // take what's left for the title
-
-            item_list.push(item.clone());
+    // read some string from the command line as user input
+    fn read(&self, question: &str) -> Result<String, TodoError> {
+        if self.to_stderr {
+            eprint!("{question}");
+            io::stderr().flush().map_err(TodoError::Input)?;
+        } else {
+            print!("{question}");
+            io::stdout().flush().map_err(TodoError::Input)?;
         }
+        let mut buffer = String::new();
+        io::stdin()
+            .read_line(&mut buffer)
+            .map_err(TodoError::Input)?;
+        // remove trailing newline
+        Ok(buffer.trim().to_string())
     }
-
-    item_list
 }
 
-#[derive(Debug, Clone)]
-pub struct TodoItem {
-    pub id: usize,
-    pub line: usize, // should probably be a tuple of start/end (see logseq task block would remain junk)
-    pub is_completed: bool,
-    pub title: String,
-    pub date_due: Option<NaiveDate>,
-    pub tags: Vec<String>,
-    pub names: Vec<String>,
-}
-impl TodoItem {
-    pub fn new() -> TodoItem {
-        TodoItem {
-            id: 0,
-            line: 0,
-            is_completed: false,
-            title: String::from(""),
-            date_due: None,
-            tags: Vec::new(),
-            names: Vec::new(),
-        }
-    }
-
-    pub fn get_string(todoitem: &TodoItem, conf_todo: &TodoConfig) -> String {
-        let mut result_string = String::new();
-        result_string.push_str(if todoitem.is_completed {
-            &conf_todo.example_done
-        } else {
-            &conf_todo.example_todo
-        });
-
-        result_string.push(' ');
-        result_string.push_str(&todoitem.title);
-        result_string.push(' ');
-        if let Some(date) = &todoitem.date_due {
-            result_string.push_str(date.to_string().as_str());
-        }
-        if &todoitem.tags.len() > &0 {
-            for tag in &todoitem.tags {
-                result_string.push_str(&format!(" {tag}"))
+/// Check config. If there is none, let user configure manually.
+/// The todo file is taken from the first of: `todo_file` (`--file` or `TODO_FILE`),
+/// a `.todo.toml` or `TODO.md` in the current directory or its parents, the global config.
+pub fn init_config(prompt: &Prompt, todo_file: Option<&Path>) -> Result<ConfigFile, TodoError> {
+    // setting some defaults
+    let mut conf = ConfigFile::default();
+    let path_to_conf = ConfigFile::default_path().ok_or_else(|| TodoError::Config {
+        path: PathBuf::from("~/.config/todo-md-rs/config.toml"),
+        message: "unable to find your config directory".to_string(),
+    })?;
+    let local = match todo_file {
+        Some(_) => None,
+        None => std::env::current_dir().ok().and_then(|dir| discover(&dir)),
+    };
+    // only the global config is created and updated interactively
+    let uses_global = todo_file.is_none() && local.is_none();
+
+    if let Some(Discovered::Config(local_conf)) = &local {
+        conf = ConfigFile::load(local_conf)?;
+    } else if check_dir_exists(&path_to_conf)? {
+        conf = ConfigFile::load(&path_to_conf)?;
+    } else if uses_global {
+        match prompt
+            .ask("create base configuraton file? ( [y]es / [n]o ): ")?
+            .as_str()
+        {
+            "y" | "yes" => {
+                create_path(&path_to_conf, prompt)?;
+                conf.save(&path_to_conf)?;
             }
-        }
-        if &todoitem.names.len() > &0 {
-            for name in &todoitem.names {
-                result_string.push_str(&format!(" {name}"))
+            _ => {
+                prompt.notice("using temporary path");
+                let toml = toml::to_string(&conf).unwrap();
+                prompt.notice(&format!("{toml:#?}"));
             }
         }
+    }
+    if !CHECKBOX_STYLES.contains(&conf.format.checkbox_style.as_str()) {
+        prompt.notice("Be careful: your config contains an invalid format! Defaulting to \"md\".");
+    }
 
-        result_string
+    if let Some(path) = todo_file {
+        conf.set_todo_file(path);
+    } else if let Some(Discovered::TodoFile(path)) = &local {
+        conf.set_todo_file(path);
     }
-}
 
-pub struct TodoConfig {
-    pub completion_style: Regex, // check if line is valid
-    pub completion_done: Regex,  // check if valid line is done
-    pub date_format: Regex,
-    pub example_todo: String,
-    pub example_done: String,
-}
-impl TodoConfig {
-    pub fn new(conf_file: &ConfigFile) -> TodoConfig {
-        let default_md: TodoConfig = TodoConfig {
-            completion_style: Regex::new(r"^\s*-\s*\[[ xX]\]").unwrap(),
-            completion_done: Regex::new(r"^\s*-\s*\[[^\s]\]").unwrap(),
-            date_format: Regex::new(r"(?:^|\s)(\d{4}-\d{2}-\d{2})(?:\s|$)").unwrap(),
-            example_todo: String::from("- [ ]"),
-            example_done: String::from("- [X]"),
-        };
+    // onto checking for the todo.md file itself
+
+    let mut unified_path: PathBuf = conf.todo_file();
 
-        // Markdown style
-        if conf_file.format.checkbox_style == "md" {
-            default_md
-        }
-        // Logseq style
-        else if conf_file.format.checkbox_style == "logseq" {
-            TodoConfig {
-                completion_style: Regex::new(r"^\s*-\s*[A-Z]{4}").unwrap(),
-                completion_done: Regex::new(r"^\s*-\s*DONE\s").unwrap(),
-                date_format: default_md.date_format,
-                example_todo: String::from("- TODO"),
-                example_done: String::from("- DONE"),
+    while !check_dir_exists(&unified_path)? {
+        let question = if uses_global {
+            format!(
+                "create {} ?\n( [y]es / [n]o / [c]hange ): ",
+                unified_path.display()
+            )
+        } else {
+            format!("create {} ?\n( [y]es / [n]o ): ", unified_path.display())
+        };
+        match prompt.ask(&question)?.as_str() {
+            "y" | "yes" => create_path(&unified_path, prompt)?,
+            "c" | "change" if uses_global => {
+                prompt.notice("so you want to change?");
+                let new_path = PathBuf::from(
+                    prompt.read("desired path to todo.md file (including filename): ")?,
+                );
+                let (Some(parent), Some(filename)) = (new_path.parent(), new_path.file_name())
+                else {
+                    prompt.notice(&format!(
+                        "{} is not a valid path to a file",
+                        new_path.display()
+                    ));
+                    continue;
+                };
+                conf.path.todo_filename = filename.into();
+                conf.path.todo_path = parent.into();
+                unified_path = conf.todo_file();
+                // create the todo.md file
+                create_path(&unified_path, prompt)?;
+                // update the config file
+                conf.save(&path_to_conf)?;
             }
-        }
-        // default to Markdown
-        else {
-            println!("Be careful: your config contains an invalid format! Defaulting to \"md\".");
-            return default_md;
+            _ => return Err(TodoError::Aborted),
         }
     }
+    Ok(conf)
 }
 
-pub fn remove_lines(filepath: &PathBuf, line_nr: Vec<usize>) {
-    let original_content = fs::read_to_string(filepath).expect("file not found");
-    let indices_zero_indexed: Vec<usize> = line_nr
-        .iter()
-        .map(|&nr| if nr < 1 { nr } else { nr - 1 })
-        .collect();
-
-    let lines: Vec<String> = original_content
-        .lines()
-        .enumerate()
-        .filter_map(|(i, line)| {
-            // line number is 1-indexed but we'll remove by counting 0-indexed
-            if !indices_zero_indexed.contains(&i) {
-                Some(line.to_string())
-            } else {
-                None
-            }
-        })
-        .collect();
-
-    let mut file = File::create(filepath).unwrap();
-    for line in lines {
-        let _ = writeln!(file, "{}", line);
+/// All tasks of `todo_files`, only parsing the files that changed since the last time.
+/// `rebuild` starts over with an empty index. The index is only a cache,
+/// so failing to write it is just a warning.
+pub fn indexed_tasks(
+    todo_files: &[PathBuf],
+    conf_file: &ConfigFile,
+    conf_todo: &TodoConfig,
+    rebuild: bool,
+) -> Result<Vec<TodoItem>, TodoError> {
+    let vault = conf_file.vault_dir();
+    let Some(index_path) = TaskIndex::default_path() else {
+        let workspace = Workspace::load(todo_files, vault.as_deref())?;
+        warn_skipped(&workspace.skipped);
+        return Ok(workspace.tasks(conf_todo));
+    };
+    let style = &conf_file.format.checkbox_style;
+    let mut index = if rebuild {
+        TaskIndex::new(style)
+    } else {
+        TaskIndex::load(&index_path, style)
+    };
+    let tasks = index.tasks(todo_files, vault.as_deref(), conf_todo)?;
+    warn_skipped(index.skipped());
+    if let Err(e) = index.save(&index_path) {
+        eprintln!("warning: unable to update the index: {e}");
     }
+    Ok(tasks)
 }
 
-pub fn change_line(filepath: &PathBuf, line_nr: usize, line_content: String) {
-    // TODO: Avoid reading from the filesystem at every call. Batch all of it into one operation.
-    let original_content = fs::read_to_string(filepath).expect("file not found");
-    let line_nr_zero_indexed: usize = if line_nr < 1 { line_nr } else { line_nr - 1 };
-
-    let lines: Vec<String> = original_content
-        .lines()
-        .enumerate()
-        .filter_map(|(i, line)| {
-            // catch the line to be changed
-            if i != line_nr_zero_indexed as usize {
-                Some(line.to_string()) // existed before
-            } else {
-                Some(line_content.clone()) // was changed
-            }
-        })
-        .collect();
-
-    let mut file = File::create(filepath).unwrap();
-    for line in lines {
-        let _ = writeln!(file, "{}", line);
+/// Tells the user about notes that were left out because they aren't valid UTF-8.
+pub fn warn_skipped(skipped: &[PathBuf]) {
+    for path in skipped {
+        eprintln!("warning: skipping {}, it isn't valid UTF-8", path.display());
     }
 }
 
-pub fn read_lines(filepath: &PathBuf) -> Vec<String> {
-    // let parser = crate::todo::TodoParser::new();
-    let mut lines: Vec<String> = Vec::new();
-    let file = fs::read_to_string(filepath).expect("file not found");
-    for line in file.lines() {
-        lines.push(line.to_string())
+/// Prints the name, query, sorting and grouping of every view.
+pub fn list_views(views: &BTreeMap<String, ViewConfig>, todos: &Todo) {
+    if todos.output != OutputFormat::Text {
+        for (name, view) in views {
+            todos.emit(json!({
+                "name": name,
+                "query": view.query,
+                "sort": view.sort,
+                "group_by": view.group_by,
+            }));
+        }
+        return;
     }
 
-    lines
-}
-
-/// `append: false` would overwrite the existing content
-pub fn export_line(filepath: &PathBuf, line_content: String, append: bool) -> std::io::Result<()> {
-    let mut file = fs::OpenOptions::new()
-        .write(true)
-        .append(append)
-        .open(filepath)?;
-    file.write_all(format!("{line_content}\n").as_bytes())?;
-
-    let _newone = fs::read_to_string(filepath).unwrap();
-
-    Ok(())
+    let width = views.keys().map(String::len).max().unwrap_or(0);
+    for (name, view) in views {
+        let mut line = format!("{:width$}  {}", name.bold(), view.query);
+        if let Some(sort) = view.sort {
+            line = format!("{line}{}{}", " | ", format!("sorted by {sort}").dimmed());
+        }
+        if let Some(group_by) = view.group_by {
+            line = format!(
+                "{line}{}{}",
+                " | ",
+                format!("grouped by {group_by}").dimmed()
+            );
+        }
+        println!("{line}");
+    }
 }
 
-pub fn check_dir_exists(path: &PathBuf) -> bool {
-    match path.try_exists() {
-        Ok(true) => {
-            // just return true?
-            return true;
-        }
-        Ok(false) => {
-            // just return false?
-            return false;
+// creates file and parent path
+pub fn create_path(path: &Path, prompt: &Prompt) -> Result<(), TodoError> {
+    match check_dir_exists(path)? {
+        false => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).map_err(TodoError::io(parent))?;
+            }
+            prompt.notice(&format!("creating: {path:?}"));
+            File::create(path).map_err(TodoError::io(path))?;
         }
-        Err(e) => {
-            // error
-            println!("attention: {e:?}");
-            panic!("unhandled error occured: tried checking for dir");
+        true => {
+            prompt.notice("file already exists");
         }
     }
+    Ok(())
 }
-// read some string from the command line as user input
-pub fn readinput(prompt: &str) -> io::Result<String> {
-    let mut buffer = String::new();
-    print!("{prompt}");
-    io::stdout().flush()?;
-    let stdin = io::stdin();
-    stdin.read_line(&mut buffer)?;
-    // remove trailing newline
-    let input = buffer.trim().to_string();
-
-    Ok(input)
+
+pub fn check_dir_exists(path: &Path) -> Result<bool, TodoError> {
+    path.try_exists().map_err(TodoError::io(path))
 }
 
 pub struct Todo {
     pub todo_list: Vec<TodoItem>,
+    pub files_root: Option<PathBuf>, // show where each task is from, relative to this directory
+    pub output: OutputFormat,
+    json_output: RefCell<Vec<Value>>, // collected until `finish` for `OutputFormat::Json`
 }
 impl Todo {
     pub fn new() -> Todo {
         Todo {
             todo_list: Vec::new(),
+            files_root: None,
+            output: OutputFormat::Text,
+            json_output: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Prints a record right away for ndjson or keeps it for the array printed by `finish`.
+    pub fn emit(&self, record: Value) {
+        match self.output {
+            OutputFormat::Text => (),
+            OutputFormat::Json => self.json_output.borrow_mut().push(record),
+            OutputFormat::Ndjson => println!("{record}"),
+        }
+    }
+
+    /// Prints the JSON array. Without anything to report it's empty, but it's always there.
+    pub fn finish(&self) {
+        if self.output == OutputFormat::Json {
+            println!("{}", Value::Array(self.json_output.take()));
+        }
+    }
+
+    /// Tells what happened to `item`: `prefix` is shown to humans, `action` is the word for scripts.
+    fn report(&self, item: &TodoItem, prefix: &str, action: &str) {
+        match self.output {
+            OutputFormat::Text => self.list_single(item, prefix),
+            _ => self.emit(json!({ "action": action, "task": TaskJson::from(item) })),
+        }
+    }
+
+    /// Maps positional or stable IDs to indices of `todo_list`.
+    /// Fails if any of them doesn't exist, so nothing is changed by accident.
+    fn resolve(&self, selectors: &[String]) -> Result<Vec<usize>, TodoError> {
+        let mut positions: Vec<usize> = Vec::new();
+        for selector in selectors {
+            match self
+                .todo_list
+                .iter()
+                .position(|item| item.matches_selector(selector))
+            {
+                Some(pos) if !positions.contains(&pos) => positions.push(pos),
+                Some(_) => (),
+                None => return Err(TodoError::TaskNotFound(selector.clone())),
+            }
         }
+        Ok(positions)
+    }
+
+    /// The task with this positional or stable ID.
+    pub fn find(&self, selector: &str) -> Result<&TodoItem, TodoError> {
+        let pos = self.resolve(&[selector.to_string()])?[0];
+        Ok(&self.todo_list[pos])
     }
 
-    /// input_content is of type `&Vec<String>` because that's what clap uses to capture all argument after the command
-    pub fn add(&self, input_content: &Vec<String>, conf_todo: &TodoConfig, conf_file: &ConfigFile) {
-        let mut item: TodoItem = strings_to_todo(
-            vec![format!(
-                "{} {}",
-                conf_todo.example_todo,
-                input_content.join(" ")
-            )],
-            &conf_todo,
-        )
-        .get(0)
-        .expect("unable to convert your input into a task, sorry")
-        .to_owned();
-        item.id = self.todo_list.len() + 1;
+    /// input_content is a slice of `String`s because that's what clap uses to capture all argument after the command
+    /// `section` inserts the task at the end of the heading with that name instead of the end of the file.
+    /// `properties` are set on top of what the input contains, like `--due` and `--priority`.
+    pub fn add(
+        &self,
+        input_content: &[String],
+        section: Option<&str>,
+        properties: &TaskEdit,
+        conf_todo: &TodoConfig,
+        workspace: &mut Workspace,
+    ) -> Result<(), TodoError> {
+        let mut item = TodoItem::from_input(&input_content.join(" "), conf_todo, &self.todo_list)?;
+        if !properties.is_empty() {
+            item.apply(properties, conf_todo)?;
+        }
+        workspace.add_task(&mut item, section, conf_todo)?;
 
-        let _ = export_line(
-            &conf_file
-                .path
-                .todo_path
-                .join(conf_file.path.todo_filename.clone()),
-            TodoItem::get_string(&item, conf_todo),
-            true,
-        );
+        self.report(&item, "adding", "added");
+        Ok(())
+    }
+
+    /// Indices of all subtasks of the task at `pos`, recursively.
+    fn descendants(&self, pos: usize) -> Vec<usize> {
+        let mut result: Vec<usize> = Vec::new();
+        for child in &self.todo_list[pos].children {
+            if let Some(child_pos) = self.todo_list.iter().position(|item| item.id == *child) {
+                result.push(child_pos);
+                result.extend(self.descendants(child_pos));
+            }
+        }
+        result
+    }
 
-        Self::list_single(&item, "adding");
+    /// Gives the task at `pos` a stable ID unless it already has one, so tasks written in
+    /// another app can be found again once they were edited through `todo`.
+    fn ensure_stable_id(&mut self, pos: usize, conf_todo: &TodoConfig) -> Result<(), TodoError> {
+        if self.todo_list[pos].stable_id.is_some() {
+            return Ok(());
+        }
+        let taken: Vec<String> = self
+            .todo_list
+            .iter()
+            .filter_map(|item| item.stable_id.clone())
+            .collect();
+        let taken: Vec<&str> = taken.iter().map(String::as_str).collect();
+        self.todo_list[pos].assign_stable_id(&taken, conf_todo)
     }
 
-    pub fn done(&mut self, indicies: &Vec<usize>, conf_file: &ConfigFile, conf_todo: &TodoConfig) {
+    /// `recursive` also checks off all open subtasks of the given tasks.
+    /// `stamp_done_date` adds today's date as the completion date.
+    pub fn done(
+        &mut self,
+        ids: &[String],
+        recursive: bool,
+        stamp_done_date: bool,
+        conf_todo: &TodoConfig,
+        workspace: &mut Workspace,
+    ) -> Result<(), TodoError> {
         // to keep track of valid ids
         let mut to_check_off: Vec<usize> = Vec::new();
-        let mut to_uncheck: Vec<usize> = Vec::new();
-
-        // sanitize input
-        for item in indicies {
-            if item <= &self.todo_list.len() && item > &0 {
-                // valid, can remove safely
-                if self.todo_list[item - 1].is_completed {
-                    to_uncheck.push(*item);
-                } else {
-                    to_check_off.push(item - 1);
-                }
-            } else {
-                println!("argument {item} is out of range");
+        let mut to_uncheck: Vec<String> = Vec::new();
+
+        for pos in self.resolve(ids)? {
+            if self.todo_list[pos].is_completed {
+                to_uncheck.push(self.todo_list[pos].id.to_string());
+                continue;
+            }
+            let open_children: Vec<usize> = self
+                .descendants(pos)
+                .into_iter()
+                .filter(|child| !self.todo_list[*child].is_completed)
+                .collect();
+
+            if !to_check_off.contains(&pos) {
+                to_check_off.push(pos);
+            }
+            if recursive {
+                to_check_off.extend(
+                    open_children
+                        .iter()
+                        .filter(|child| !to_check_off.contains(child))
+                        .collect::<Vec<_>>(),
+                );
+            } else if !open_children.is_empty() {
+                eprintln!(
+                    "warning: task {} still has {} open subtask(s)",
+                    self.todo_list[pos].id,
+                    open_children.len()
+                );
             }
         }
 
         // write into file and list
+        let today = Local::now().date_naive();
+        let mut new_ids: Vec<String> = Vec::new();
+        let mut inserted_after: Vec<usize> = Vec::new(); // positional IDs the next occurrences were inserted after
         for pos in to_check_off {
-            self.todo_list[pos].is_completed = true;
-            change_line(
-                &conf_file
-                    .path
-                    .todo_path
-                    .join(conf_file.path.todo_filename.clone()),
-                self.todo_list[pos].line,
-                TodoItem::get_string(&self.todo_list[pos], conf_todo),
-            );
-
-            Self::list_single(&self.todo_list[pos], "done");
+            workspace.set_completed(&mut self.todo_list[pos], true, conf_todo);
+            if stamp_done_date {
+                let item = &mut self.todo_list[pos];
+                item.set_done_date(Some(today), conf_todo)?;
+                workspace.update_task(item, conf_todo);
+            }
+            self.report(&self.todo_list[pos], "done", "done");
+
+            // recurring tasks come back right below the completed one and its subtasks
+            let taken: Vec<&str> = self
+                .todo_list
+                .iter()
+                .filter_map(|item| item.stable_id.as_deref())
+                .chain(new_ids.iter().map(String::as_str))
+                .collect();
+            let recurs = self.todo_list[pos].recurrence.is_some();
+            match self.todo_list[pos].next_occurrence(today, &taken, conf_todo)? {
+                None if recurs => eprintln!(
+                    "warning: task {} has no date left to recur on",
+                    self.todo_list[pos].id
+                ),
+                None => (),
+                Some(mut next) => {
+                    let last = self
+                        .descendants(pos)
+                        .into_iter()
+                        .chain([pos])
+                        .max_by_key(|other| self.todo_list[*other].line)
+                        .unwrap_or(pos);
+                    let after = self.todo_list[last].id;
+                    next.id = after + 1 + inserted_after.iter().filter(|id| **id <= after).count();
+                    inserted_after.push(after);
+                    workspace.insert_task_after(&mut next, &self.todo_list[last], conf_todo);
+                    new_ids.extend(next.stable_id.clone());
+                    self.report(&next, "next", "added");
+                }
+            }
         }
 
-        if to_uncheck.len() > 0 {
-            self.uncheck(&to_uncheck, conf_file, conf_todo)
+        if !to_uncheck.is_empty() {
+            self.uncheck(&to_uncheck, stamp_done_date, conf_todo, workspace)?;
         };
+        Ok(())
     }
 
-    pub fn uncheck(&mut self, ids: &Vec<usize>, conf_file: &ConfigFile, conf_todo: &TodoConfig) {
-        let mut to_uncheck: Vec<usize> = Vec::new(); // to store sanitized ids
-
-        // sanitize input
-        for item in ids {
-            if item <= &self.todo_list.len() && item > &0 {
-                to_uncheck.push(item - 1); // valid, can remove safely
-            } else {
-                println!("argument {item} is out of range");
+    /// `stamp_done_date` removes the completion date again, a todo.txt one is always removed.
+    pub fn uncheck(
+        &mut self,
+        ids: &[String],
+        stamp_done_date: bool,
+        conf_todo: &TodoConfig,
+        workspace: &mut Workspace,
+    ) -> Result<(), TodoError> {
+        // write into file and list
+        for pos in self.resolve(ids)? {
+            workspace.set_completed(&mut self.todo_list[pos], false, conf_todo);
+            let item = &mut self.todo_list[pos];
+            if stamp_done_date || item.has_todotxt_done_date() {
+                item.set_done_date(None, conf_todo)?;
+                workspace.update_task(item, conf_todo);
             }
+
+            self.report(&self.todo_list[pos], "unchecked", "unchecked");
         }
+        Ok(())
+    }
 
-        // write into file and list
-        for pos in to_uncheck {
-            self.todo_list[pos].is_completed = false;
-            change_line(
-                &conf_file
-                    .path
-                    .todo_path
-                    .join(conf_file.path.todo_filename.clone()),
-                self.todo_list[pos].line,
-                TodoItem::get_string(&self.todo_list[pos], conf_todo),
-            );
+    /// Changes a single task, see `TaskEdit`.
+    pub fn edit(
+        &mut self,
+        id: &str,
+        edit: &TaskEdit,
+        conf_todo: &TodoConfig,
+        workspace: &mut Workspace,
+    ) -> Result<(), TodoError> {
+        let pos = self.resolve(&[id.to_string()])?[0];
+        if edit.is_empty() {
+            self.report(&self.todo_list[pos], "nothing to change", "unchanged");
+            return Ok(());
+        }
+
+        self.todo_list[pos].apply(edit, conf_todo)?;
+        self.ensure_stable_id(pos, conf_todo)?;
+        workspace.update_task(&self.todo_list[pos], conf_todo);
+
+        self.report(&self.todo_list[pos], "edited", "edited");
+        Ok(())
+    }
+
+    /// Lets the user change the line of a task in their editor and returns the new line.
+    /// Nothing is written, that's up to `edit_line`, so the files don't have to be locked meanwhile.
+    pub fn edit_in_editor(&self, id: &str) -> Result<String, TodoError> {
+        let item = self.find(id)?;
+        let (temp_path, mut file) = create_temp_file()?;
+        let written = writeln!(file, "{}", item.raw).map_err(TodoError::io(&temp_path));
+        drop(file);
+
+        let edited = written
+            .and_then(|()| crate::editor::open_at(&temp_path, 1))
+            .and_then(|()| std::fs::read_to_string(&temp_path).map_err(TodoError::io(&temp_path)));
+        let _ = std::fs::remove_file(&temp_path);
+        let edited = edited?;
+
+        // exactly one line, leading whitespace is the indentation
+        let mut lines = edited.lines().filter(|line| !line.trim().is_empty());
+        match (lines.next(), lines.next()) {
+            (None, _) => Err(TodoError::Aborted),
+            (Some(line), None) => Ok(line.trim_end().to_string()),
+            (Some(_), Some(_)) => Err(TodoError::InvalidTask(edited.trim().to_string())),
+        }
+    }
 
-            Self::list_single(&self.todo_list[pos], "unchecked");
-        }
-    }
-
-    // TODO: Notice how we're currently removing by line number, not by task id? The api should be more robust than that.
-    pub fn remove(&self, ids: &Vec<usize>, path: PathBuf) {
-        let mut delete_all = false;
-        let mut lines_to_rm: Vec<usize> = Vec::new();
-        for item in &self.todo_list {
-            if ids.contains(&item.id) {
-                Self::list_single(&item, "to remove");
-                if !delete_all {
-                    match readinput("delete that task? ( [y]es / [n]o / [a]ll ): ")
-                        .expect("input failed")
-                        .as_str()
-                    {
-                        "y" | "yes" => lines_to_rm.push(item.line),
-                        "a" | "all" => {
-                            delete_all = true;
-                            lines_to_rm.push(item.line);
-                        }
-                        _ => (),
-                    }
-                } else {
-                    lines_to_rm.push(item.line);
+    /// Replaces the whole line of a task, e.g. with the one from `edit_in_editor`.
+    pub fn edit_line(
+        &mut self,
+        id: &str,
+        line: &str,
+        conf_todo: &TodoConfig,
+        workspace: &mut Workspace,
+    ) -> Result<(), TodoError> {
+        let pos = self.resolve(&[id.to_string()])?[0];
+        if line == self.todo_list[pos].raw {
+            self.report(&self.todo_list[pos], "nothing to change", "unchanged");
+            return Ok(());
+        }
+
+        self.todo_list[pos].replace_line(line, conf_todo)?;
+        self.ensure_stable_id(pos, conf_todo)?;
+        workspace.update_task(&self.todo_list[pos], conf_todo);
+
+        self.report(&self.todo_list[pos], "edited", "edited");
+        Ok(())
+    }
+
+    /// `confirm: false` removes the tasks without asking.
+    pub fn remove(
+        &self,
+        ids: &[String],
+        confirm: bool,
+        prompt: &Prompt,
+        conf_todo: &TodoConfig,
+        workspace: &mut Workspace,
+    ) -> Result<(), TodoError> {
+        let mut delete_all = !confirm;
+        for pos in self.resolve(ids)? {
+            let item = &self.todo_list[pos];
+            if self.output == OutputFormat::Text {
+                self.list_single(item, if confirm { "to remove" } else { "removing" });
+            }
+            if !delete_all {
+                match prompt
+                    .ask("delete that task? ( [y]es / [n]o / [a]ll ): ")?
+                    .as_str()
+                {
+                    "y" | "yes" => (),
+                    "a" | "all" => delete_all = true,
+                    _ => continue,
                 }
             }
+            workspace.remove_task(item, conf_todo);
+            // scripts only hear about tasks that were actually removed
+            if self.output != OutputFormat::Text {
+                self.report(item, "", "removed");
+            }
         }
-        remove_lines(&path, lines_to_rm);
+        Ok(())
     }
 
     /// `prefix` is prepended with ": " or ignored if empty
-    fn list_single(item: &TodoItem, prefix: &str) {
-        let mut line: ColoredString = "".into();
-
-        if !prefix.is_empty() {
-            line = format!("{prefix}: ").into();
-        }
+    fn list_single(&self, item: &TodoItem, prefix: &str) {
+        let mut line: ColoredString = if !prefix.is_empty() {
+            format!("{prefix}: ").into()
+        } else {
+            // render subtasks as a tree
+            "  ".repeat(item.depth).into()
+        };
 
         // every task has these
         if item.is_completed {
@@ -523,8 +587,35 @@ impl Todo {
         line = format!("{line} {} {}", item.id, item.title).into();
 
         // optional parts
-        if let Some(date) = item.date_due {
-            line = format!("{line}{}{}", " | ", date.to_string().red()).into();
+        if let Some(priority) = item.priority {
+            let label = format!("!{priority}");
+            line = match priority {
+                Priority::Highest | Priority::High => format!("{line}{}{}", " | ", label.yellow()),
+                _ => format!("{line}{}{}", " | ", label.dimmed()),
+            }
+            .into();
+        }
+        if let Some(due) = item.date_due {
+            let mut date = due.to_string();
+            if !item.is_completed {
+                if let Some(left) = time_left(&due, Local::now().naive_local()) {
+                    date = format!("{date} ({left})");
+                }
+            }
+            line = format!("{line}{}{}", " | ", date.red()).into();
+        }
+        if let Some(rule) = item.recurrence {
+            line = format!("{line}{}{}", " | ", format!("🔁 {rule}").blue()).into();
+        }
+        for kind in [
+            DateKind::Scheduled,
+            DateKind::Start,
+            DateKind::Created,
+            DateKind::Done,
+        ] {
+            if let Some(date) = item.date(kind) {
+                line = format!("{line}{}{}", " | ", format!("{kind} {date}").dimmed()).into();
+            }
         }
         if !item.tags.is_empty() {
             line = format!("{line}{}{}", " | ", item.tags.join(" ").green()).into();
@@ -532,6 +623,14 @@ impl Todo {
         if !item.names.is_empty() {
             line = format!("{line}{}{}", " | ", item.names.join(" ").cyan()).into();
         }
+        if let Some(stable_id) = &item.stable_id {
+            line = format!("{line}{}{}", " | ", format!("^{stable_id}").dimmed()).into();
+        }
+        if let Some(root) = &self.files_root {
+            let file = item.file.strip_prefix(root).unwrap_or(&item.file);
+            let location = format!("{}:{}", file.display(), item.line);
+            line = format!("{line}{}{}", " | ", location.dimmed()).into();
+        }
 
         // modifications to the whole string
         if item.is_completed {
@@ -541,9 +640,107 @@ impl Todo {
         println!("{line}");
     }
 
-    pub fn list_all(&self) {
-        for it in &self.todo_list {
-            Todo::list_single(it, "");
+    /// Lists all tasks matching `query`, under a heading for each group if `group_by` is given.
+    pub fn list_all(&self, query: &Query, group_by: Option<GroupKey>) {
+        let matching = self.todo_list.iter().filter(|it| query.matches(it));
+        let Some(key) = group_by else {
+            for it in matching {
+                match self.output {
+                    OutputFormat::Text => self.list_single(it, ""),
+                    _ => self.emit(json!(TaskJson::from(it))),
+                }
+            }
+            return;
+        };
+
+        let mut groups: BTreeMap<String, Vec<&TodoItem>> = BTreeMap::new();
+        let mut missing: Vec<&TodoItem> = Vec::new();
+        for it in matching {
+            let labels = key.labels(it);
+            if labels.is_empty() {
+                missing.push(it);
+            }
+            for label in labels {
+                groups.entry(label).or_default().push(it);
+            }
+        }
+        // tasks without a label come last
+        let missing_group =
+            (!missing.is_empty()).then(|| (key.missing_label().to_string(), missing));
+
+        for (i, (label, items)) in groups.into_iter().chain(missing_group).enumerate() {
+            if self.output != OutputFormat::Text {
+                let tasks: Vec<TaskJson> = items.into_iter().map(TaskJson::from).collect();
+                self.emit(json!({ "group": label, "tasks": tasks }));
+                continue;
+            }
+            if i > 0 {
+                println!();
+            }
+            self.list_group_header(key, &label, items.len());
+            for it in items {
+                self.list_single(it, "");
+            }
         }
     }
+
+    /// The label in the colour `list_single` uses for that part of a task, followed by the number of tasks.
+    fn list_group_header(&self, key: GroupKey, label: &str, count: usize) {
+        let label: ColoredString = match key {
+            GroupKey::Tag => label.green(),
+            GroupKey::Name => label.cyan(),
+            GroupKey::Date => label.red(),
+            GroupKey::Section => label.into(),
+            GroupKey::File => {
+                let file = Path::new(label);
+                let root = self.files_root.as_deref().unwrap_or(Path::new(""));
+                file.strip_prefix(root)
+                    .unwrap_or(file)
+                    .display()
+                    .to_string()
+                    .dimmed()
+            }
+        };
+        println!("{} {}", label.bold(), format!("({count})").dimmed());
+    }
+}
+
+/// Creates a new file for `edit_in_editor` in the temp dir, readable only by the user.
+/// The temp dir may be shared, so an existing file or a link someone put there in advance
+/// is never opened, another name is tried instead.
+fn create_temp_file() -> Result<(PathBuf, File), TodoError> {
+    let dir = std::env::temp_dir();
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.subsec_nanos())
+        .unwrap_or_default();
+    let mut attempt: u32 = 0;
+    loop {
+        let name = format!("todo-edit-{}-{nanos:x}-{attempt}.md", std::process::id());
+        let path = dir.join(name);
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(e) => return Err(TodoError::io(path)(e)),
+        }
+    }
+}
+
+/// How long until `due` if it's at a time today, like `in 2h 15m` or `15m overdue`.
+fn time_left(due: &Due, now: NaiveDateTime) -> Option<String> {
+    let deadline = due.local_time().filter(|time| time.date() == now.date())?;
+    let minutes = (deadline - now).num_minutes();
+    let duration = match minutes.abs() {
+        0..=59 => format!("{}m", minutes.abs()),
+        abs => format!("{}h {}m", abs / 60, abs % 60),
+    };
+    Some(if minutes >= 0 {
+        format!("in {duration}")
+    } else {
+        format!("{duration} overdue")
+    })
 }
@@ -0,0 +1,212 @@
+//! Finding all notes of an Obsidian or Logseq vault.
+
+use crate::error::TodoError;
+use crate::workspace::{is_hidden, sorted_entries, wildcard_match};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directories of a Logseq graph that contain notes, the rest is settings and backups.
+pub(crate) const LOGSEQ_DIRS: [&str; 2] = ["pages", "journals"];
+/// Files with exclude patterns in `.gitignore` syntax, read in every directory of a vault.
+pub(crate) const IGNORE_FILES: [&str; 2] = [".gitignore", ".todoignore"];
+
+/// All Markdown files below `root`, sorted by path.
+/// Hidden files and directories (`.obsidian`, `.git`, ...) and everything excluded by an
+/// ignore file are skipped. In a Logseq graph only `pages/` and `journals/` are searched.
+pub fn vault_files(root: &Path) -> Result<Vec<PathBuf>, TodoError> {
+    fs::read_dir(root).map_err(TodoError::io(root))?;
+
+    let mut found: Vec<PathBuf> = Vec::new();
+    let mut rules: Vec<IgnoreRule> = Vec::new();
+    if is_logseq_graph(root) {
+        read_ignore_files(root, &mut rules);
+        for dir in LOGSEQ_DIRS {
+            let dir = root.join(dir);
+            if dir.is_dir() && !is_ignored(&dir, &rules) {
+                walk(&dir, &mut rules, &mut found);
+            }
+        }
+    } else {
+        walk(root, &mut rules, &mut found);
+    }
+    Ok(found)
+}
+
+/// Logseq keeps its settings in a `logseq/` directory next to the notes.
+pub fn is_logseq_graph(root: &Path) -> bool {
+    root.join("logseq").is_dir() && LOGSEQ_DIRS.iter().any(|dir| root.join(dir).is_dir())
+}
+
+fn walk(dir: &Path, rules: &mut Vec<IgnoreRule>, found: &mut Vec<PathBuf>) {
+    // rules of an ignore file only apply to its own directory
+    let inherited = rules.len();
+    read_ignore_files(dir, rules);
+
+    for entry in sorted_entries(dir) {
+        if is_hidden(&entry) || is_ignored(&entry, rules) {
+            continue;
+        }
+        if entry.is_dir() {
+            walk(&entry, rules, found);
+        } else if entry
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
+        {
+            found.push(entry);
+        }
+    }
+    rules.truncate(inherited);
+}
+
+fn read_ignore_files(dir: &Path, rules: &mut Vec<IgnoreRule>) {
+    for name in IGNORE_FILES {
+        if let Ok(content) = fs::read_to_string(dir.join(name)) {
+            rules.extend(
+                content
+                    .lines()
+                    .filter_map(|line| IgnoreRule::parse(dir, line)),
+            );
+        }
+    }
+}
+
+/// The last matching rule decides, so a later `!pattern` can bring back what an earlier one excluded.
+fn is_ignored(path: &Path, rules: &[IgnoreRule]) -> bool {
+    let is_dir = path.is_dir();
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(path, is_dir))
+        .is_some_and(|rule| !rule.negated)
+}
+
+/// One line of an ignore file.
+struct IgnoreRule {
+    base: PathBuf,      // the directory of the ignore file
+    parts: Vec<String>, // the pattern split at `/`
+    anchored: bool, // patterns containing a `/` are relative to `base`, others match names anywhere
+    dir_only: bool, // a trailing `/` only matches directories
+    negated: bool,  // a leading `!` includes what was excluded before
+}
+impl IgnoreRule {
+    fn parse(base: &Path, line: &str) -> Option<IgnoreRule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let parts: Vec<String> = line
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .map(String::from)
+            .collect();
+        if parts.is_empty() {
+            return None;
+        }
+
+        Some(IgnoreRule {
+            base: base.to_path_buf(),
+            anchored: line.contains('/'),
+            parts,
+            dir_only,
+            negated,
+        })
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        let components: Vec<String> = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+
+        if self.anchored {
+            match_parts(&self.parts, &components)
+        } else {
+            components
+                .last()
+                .is_some_and(|name| wildcard_match(&self.parts[0], name))
+        }
+    }
+}
+
+/// Matches path components against pattern components, `**` standing for any number of them.
+fn match_parts(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((part, rest)) if part == "**" => {
+            (0..=path.len()).any(|skip| match_parts(rest, &path[skip..]))
+        }
+        Some((part, rest)) => path.split_first().is_some_and(|(name, path_rest)| {
+            wildcard_match(part, name) && match_parts(rest, path_rest)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(line: &str) -> IgnoreRule {
+        IgnoreRule::parse(Path::new("/vault"), line).unwrap()
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        assert!(IgnoreRule::parse(Path::new("/vault"), "# templates").is_none());
+        assert!(IgnoreRule::parse(Path::new("/vault"), "   ").is_none());
+        assert!(IgnoreRule::parse(Path::new("/vault"), "/").is_none());
+    }
+
+    #[test]
+    fn names_match_anywhere() {
+        let templates = rule("Templates");
+        assert!(templates.matches(Path::new("/vault/Templates"), true));
+        assert!(templates.matches(Path::new("/vault/work/Templates"), true));
+        assert!(!templates.matches(Path::new("/vault/Templates.md/x"), false));
+        assert!(!templates.matches(Path::new("/elsewhere/Templates"), true));
+
+        let drafts = rule("*.draft.md");
+        assert!(drafts.matches(Path::new("/vault/a/plan.draft.md"), false));
+        assert!(!drafts.matches(Path::new("/vault/a/plan.md"), false));
+    }
+
+    #[test]
+    fn slashes_anchor_to_the_ignore_file() {
+        let archive = rule("/archive");
+        assert!(archive.matches(Path::new("/vault/archive"), true));
+        assert!(!archive.matches(Path::new("/vault/work/archive"), true));
+
+        let nested = rule("work/**/old");
+        assert!(nested.matches(Path::new("/vault/work/old"), true));
+        assert!(nested.matches(Path::new("/vault/work/2023/q1/old"), true));
+        assert!(!nested.matches(Path::new("/vault/home/old"), true));
+    }
+
+    #[test]
+    fn trailing_slash_only_matches_directories() {
+        let build = rule("build/");
+        assert!(build.matches(Path::new("/vault/build"), true));
+        assert!(!build.matches(Path::new("/vault/build"), false));
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let rules = vec![rule("*.md"), rule("!todo.md")];
+        assert!(is_ignored(Path::new("/vault/notes.md"), &rules));
+        assert!(!is_ignored(Path::new("/vault/todo.md"), &rules));
+        assert!(!is_ignored(Path::new("/vault/image.png"), &rules));
+    }
+}
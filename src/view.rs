@@ -0,0 +1,219 @@
+//! Named queries, either configured or built in.
+
+use crate::item::TodoItem;
+use crate::priority::Priority;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// What tasks can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    /// earliest due date first, tasks without one last
+    Due,
+    /// most important first, tasks without one between medium and low
+    Priority,
+    /// oldest first, tasks without a creation date last
+    Created,
+    /// alphabetically, ignoring case
+    Title,
+    /// the order of the files and the lines in them, which is the order of the positional IDs
+    File,
+}
+impl SortKey {
+    /// Sorts stably, so tasks that are equal by this key stay in the order of the file.
+    pub fn sort(&self, tasks: &mut [TodoItem]) {
+        tasks.sort_by(|a, b| self.compare(a, b));
+    }
+
+    /// Orders two tasks by this key.
+    pub fn compare(&self, a: &TodoItem, b: &TodoItem) -> Ordering {
+        match self {
+            SortKey::Due => match (a.date_due, b.date_due) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            SortKey::Created => match (a.date_created, b.date_created) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+            SortKey::Priority => Priority::rank(b.priority).cmp(&Priority::rank(a.priority)),
+            SortKey::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+            SortKey::File => a.id.cmp(&b.id),
+        }
+    }
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SortKey::Due => "due",
+            SortKey::Priority => "priority",
+            SortKey::Created => "created",
+            SortKey::Title => "title",
+            SortKey::File => "file",
+        })
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SortKey, String> {
+        match s.to_lowercase().as_str() {
+            "due" => Ok(SortKey::Due),
+            "priority" => Ok(SortKey::Priority),
+            "created" => Ok(SortKey::Created),
+            "title" => Ok(SortKey::Title),
+            "file" => Ok(SortKey::File),
+            _ => Err("expected one of: due, priority, created, title, file".to_string()),
+        }
+    }
+}
+
+/// What tasks can be grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupKey {
+    /// each tag, tasks with several of them are in several groups
+    Tag,
+    /// each assigned name, like tags
+    Name,
+    /// the headings the task is nested in
+    Section,
+    /// the day it's due
+    Date,
+    /// the file it's in
+    File,
+}
+impl GroupKey {
+    /// The groups `item` belongs to, none if it lacks the property.
+    /// Tasks with several tags or names are in the group of each of them.
+    pub fn labels(&self, item: &TodoItem) -> Vec<String> {
+        match self {
+            GroupKey::Tag => item.tags.clone(),
+            GroupKey::Name => item.names.clone(),
+            GroupKey::Section if item.section.is_empty() => Vec::new(),
+            GroupKey::Section => vec![item.section.join(" > ")],
+            GroupKey::Date => item
+                .date_due
+                .iter()
+                .map(|due| due.date().to_string())
+                .collect(),
+            GroupKey::File => vec![item.file.display().to_string()],
+        }
+    }
+
+    /// Heading of the group of tasks without any label.
+    pub fn missing_label(&self) -> &'static str {
+        match self {
+            GroupKey::Tag => "no tags",
+            GroupKey::Name => "unassigned",
+            GroupKey::Section => "no section",
+            GroupKey::Date => "no due date",
+            GroupKey::File => "no file",
+        }
+    }
+}
+
+impl fmt::Display for GroupKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GroupKey::Tag => "tag",
+            GroupKey::Name => "name",
+            GroupKey::Section => "section",
+            GroupKey::Date => "date",
+            GroupKey::File => "file",
+        })
+    }
+}
+
+impl FromStr for GroupKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GroupKey, String> {
+        match s.to_lowercase().as_str() {
+            "tag" => Ok(GroupKey::Tag),
+            "name" => Ok(GroupKey::Name),
+            "section" => Ok(GroupKey::Section),
+            "date" => Ok(GroupKey::Date),
+            "file" => Ok(GroupKey::File),
+            _ => Err("expected one of: tag, name, section, date, file".to_string()),
+        }
+    }
+}
+
+/// A saved query, `[views.<name>]` in the config.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ViewConfig {
+    /// Same syntax as for `todo list`.
+    pub query: String,
+    /// How the matching tasks are sorted, the order of the files if not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<SortKey>,
+    /// How the matching tasks are grouped, not at all if not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_by: Option<GroupKey>,
+}
+impl ViewConfig {
+    /// A view without grouping.
+    pub fn new(query: &str, sort: Option<SortKey>) -> ViewConfig {
+        ViewConfig {
+            query: query.to_string(),
+            sort,
+            group_by: None,
+        }
+    }
+}
+
+/// Views that exist without being configured. A configured view with the same name replaces them.
+pub fn builtin_views() -> BTreeMap<String, ViewConfig> {
+    BTreeMap::from([
+        (
+            "overdue".to_string(),
+            ViewConfig::new("overdue", Some(SortKey::Due)),
+        ),
+        (
+            "today".to_string(),
+            ViewConfig::new("open due:<=today", Some(SortKey::Due)),
+        ),
+        (
+            "upcoming".to_string(),
+            ViewConfig::new("open due:>today", Some(SortKey::Due)),
+        ),
+        (
+            "someday".to_string(),
+            ViewConfig::new("open due:none", None),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_order_follows_the_ids() {
+        let task = |id: usize, file: &str, title: &str| {
+            let mut item = TodoItem::new();
+            (item.id, item.file, item.title) = (id, file.into(), title.to_string());
+            item
+        };
+        // the todo file comes before the notes of the vault, no matter their names
+        let mut tasks = vec![
+            task(3, "/home/me/notes/b.md", "c"),
+            task(1, "/home/me/todo.md", "a"),
+            task(2, "/home/me/notes/a.md", "b"),
+        ];
+        SortKey::File.sort(&mut tasks);
+        let titles: Vec<&str> = tasks.iter().map(|item| item.title.as_str()).collect();
+        assert_eq!(titles, ["a", "b", "c"]);
+    }
+}
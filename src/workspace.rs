@@ -0,0 +1,313 @@
+//! Working with several todo files at once.
+
+use crate::config::TodoConfig;
+use crate::document::TodoDocument;
+use crate::error::TodoError;
+use crate::item::*;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Several todo files whose tasks are listed and edited as one.
+/// Positional IDs continue from one file to the next, edits go back to the file a task came from.
+pub struct Workspace {
+    /// The files in the order their tasks are listed. The first one is where new tasks go.
+    pub documents: Vec<TodoDocument>,
+    /// Notes of the vault that were left out, see `is_skippable`.
+    pub skipped: Vec<PathBuf>,
+}
+impl Workspace {
+    /// Reads all files at `paths`, failing if any of them can't be read.
+    /// Notes in `vault` that aren't valid UTF-8 are left out instead, see `is_skippable`.
+    pub fn load(paths: &[PathBuf], vault: Option<&Path>) -> Result<Workspace, TodoError> {
+        let mut documents: Vec<TodoDocument> = Vec::new();
+        let mut skipped: Vec<PathBuf> = Vec::new();
+        for path in paths {
+            match TodoDocument::load(path) {
+                Ok(document) => documents.push(document),
+                Err(e) if is_skippable(path, vault, &e) => skipped.push(path.clone()),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Workspace { documents, skipped })
+    }
+
+    /// All tasks of all files, in the order of the files.
+    pub fn tasks(&self, conf_todo: &TodoConfig) -> Vec<TodoItem> {
+        concat_tasks(
+            self.documents
+                .iter()
+                .map(|document| document.tasks(conf_todo)),
+        )
+    }
+
+    /// Checks whether any of the files has edits that haven't been saved yet.
+    pub fn is_modified(&self) -> bool {
+        self.documents.iter().any(TodoDocument::is_modified)
+    }
+
+    fn document_mut(&mut self, file: &Path) -> Option<&mut TodoDocument> {
+        self.documents
+            .iter_mut()
+            .find(|document| document.path == file)
+    }
+
+    /// Checks or unchecks `item` in the file it belongs to.
+    pub fn set_completed(&mut self, item: &mut TodoItem, completed: bool, conf_todo: &TodoConfig) {
+        if let Some(document) = self.document_mut(&item.file) {
+            document.set_completed(item, completed, conf_todo);
+        }
+    }
+
+    /// Writes the raw line of `item` back to the file it belongs to.
+    pub fn update_task(&mut self, item: &TodoItem, conf_todo: &TodoConfig) {
+        if let Some(document) = self.document_mut(&item.file) {
+            document.update_task(item, conf_todo);
+        }
+    }
+
+    /// Removes `item` from the file it belongs to.
+    pub fn remove_task(&mut self, item: &TodoItem, conf_todo: &TodoConfig) {
+        if let Some(document) = self.document_mut(&item.file) {
+            document.remove_task(item, conf_todo);
+        }
+    }
+
+    /// Adds `item` to the first file, or to the first file that has a heading called `section`.
+    /// Its line and positional ID are set to where it ends up, counting the tasks of the files before.
+    pub fn add_task(
+        &mut self,
+        item: &mut TodoItem,
+        section: Option<&str>,
+        conf_todo: &TodoConfig,
+    ) -> Result<(), TodoError> {
+        let pos = match section {
+            Some(name) => self
+                .documents
+                .iter()
+                .position(|document| find_section_end(document.lines(), name).is_some())
+                .ok_or_else(|| TodoError::SectionNotFound(name.to_string()))?,
+            None if self.documents.is_empty() => {
+                return Err(TodoError::InvalidTask(item.title.clone()))
+            }
+            None => 0,
+        };
+        let offset: usize = self.documents[..pos]
+            .iter()
+            .map(|document| document.tasks(conf_todo).len())
+            .sum();
+        let document = &mut self.documents[pos];
+        item.file = document.path.clone();
+        document.add_task(item, section, conf_todo)?;
+        item.id += offset;
+        Ok(())
+    }
+
+    /// Adds `item` to the file of `above`, right below it.
+    pub fn insert_task_after(
+        &mut self,
+        item: &mut TodoItem,
+        above: &TodoItem,
+        conf_todo: &TodoConfig,
+    ) {
+        if let Some(document) = self.document_mut(&above.file) {
+            item.file = above.file.clone();
+            document.insert_task_after(item, above, conf_todo);
+        }
+    }
+
+    /// Writes all modified files. If any of them can't be written because of changes by
+    /// another program, none of them are.
+    pub fn save(&mut self) -> Result<(), TodoError> {
+        for document in self.documents.iter_mut().filter(|doc| doc.is_modified()) {
+            document.sync()?;
+        }
+        for document in self.documents.iter_mut().filter(|doc| doc.is_modified()) {
+            document.save()?;
+        }
+        Ok(())
+    }
+}
+
+/// Puts the tasks of several files into one list, continuing the positional IDs.
+pub(crate) fn concat_tasks(per_file: impl Iterator<Item = Vec<TodoItem>>) -> Vec<TodoItem> {
+    let mut tasks: Vec<TodoItem> = Vec::new();
+    for file_tasks in per_file {
+        let offset = tasks.len();
+        tasks.extend(file_tasks.into_iter().map(|mut item| {
+            item.offset_ids(offset);
+            item
+        }));
+    }
+    tasks
+}
+
+/// Notes of a vault are mostly written by other apps, so one that isn't valid UTF-8 is left out
+/// instead of failing the whole command. Any other error, or any other file, still fails it.
+pub(crate) fn is_skippable(path: &Path, vault: Option<&Path>, error: &TodoError) -> bool {
+    let in_vault = vault.is_some_and(|vault| path.starts_with(vault));
+    in_vault
+        && matches!(error, TodoError::Io { source, .. } if source.kind() == io::ErrorKind::InvalidData)
+}
+
+/// Finds all files matching `pattern`, relative paths start at `base`.
+/// `*` and `?` match within a file or directory name, `**` matches any number of directories.
+pub(crate) fn expand_glob(base: &Path, pattern: &str) -> Vec<PathBuf> {
+    let pattern = Path::new(pattern);
+    let start = if pattern.is_absolute() {
+        PathBuf::from("/")
+    } else {
+        base.to_path_buf()
+    };
+    let parts: Vec<String> = pattern
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            Component::ParentDir => Some("..".to_string()),
+            _ => None,
+        })
+        .collect();
+
+    let mut found: Vec<PathBuf> = Vec::new();
+    expand_parts(&start, &parts, &mut found);
+    found.sort();
+    found.dedup();
+    found
+}
+
+fn expand_parts(dir: &Path, parts: &[String], found: &mut Vec<PathBuf>) {
+    let Some((part, rest)) = parts.split_first() else {
+        if dir.is_file() {
+            found.push(dir.to_path_buf());
+        }
+        return;
+    };
+
+    if part == "**" {
+        expand_parts(dir, rest, found);
+        for entry in sorted_entries(dir) {
+            if entry.is_dir() && !is_hidden(&entry) {
+                expand_parts(&entry, parts, found);
+            }
+        }
+    } else if part.contains(['*', '?']) {
+        for entry in sorted_entries(dir) {
+            let name = entry.file_name().unwrap_or_default().to_string_lossy();
+            if wildcard_match(part, &name) && (part.starts_with('.') || !is_hidden(&entry)) {
+                expand_parts(&entry, rest, found);
+            }
+        }
+    } else {
+        expand_parts(&dir.join(part), rest, found);
+    }
+}
+
+/// Entries of `dir` sorted by name, empty if it can't be read.
+pub(crate) fn sorted_entries(dir: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .collect()
+        })
+        .unwrap_or_default();
+    entries.sort();
+    entries
+}
+
+pub(crate) fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+/// Matches `name` against `pattern` where `*` stands for any text and `?` for a single character.
+pub(crate) fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None; // position after the last `*` and where it started matching
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((after_star, matched_from)) => {
+                    p = after_star;
+                    n = matched_from + 1;
+                    backtrack = Some((after_star, matched_from + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("*.md", "todo.md"));
+        assert!(wildcard_match("*.md", ".md"));
+        assert!(!wildcard_match("*.md", "todo.txt"));
+        assert!(wildcard_match("to?o.md", "todo.md"));
+        assert!(!wildcard_match("to?o.md", "too.md"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("?", ""));
+        // the first `*` has to give back what it took
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(wildcard_match("*ab", "aab"));
+        assert!(!wildcard_match("a*b*c", "aXbYcZ"));
+        assert!(wildcard_match("**x", "yyx"));
+    }
+
+    #[test]
+    fn globs() {
+        let dir = TempDir::new("glob");
+        for path in [
+            "todo.md",
+            "notes.txt",
+            ".hidden.md",
+            "work/a.md",
+            "work/deep/b.md",
+            "work/.git/c.md",
+            ".obsidian/d.md",
+        ] {
+            dir.write(path, "");
+        }
+        let glob = |pattern: &str| -> Vec<String> {
+            expand_glob(dir.path(), pattern)
+                .iter()
+                .map(|path| {
+                    let path = path.strip_prefix(dir.path()).unwrap_or(path);
+                    path.to_string_lossy().into_owned()
+                })
+                .collect()
+        };
+
+        assert_eq!(glob("*.md"), ["todo.md"]);
+        assert_eq!(glob(".*.md"), [".hidden.md"]);
+        assert_eq!(glob("**/*.md"), ["todo.md", "work/a.md", "work/deep/b.md"]);
+        assert_eq!(glob("work/**/b.md"), ["work/deep/b.md"]);
+        assert_eq!(glob(".obsidian/*.md"), [".obsidian/d.md"]);
+        assert_eq!(glob("work/deep/../a.md"), ["work/deep/../a.md"]);
+        assert_eq!(glob("work"), Vec::<String>::new());
+        assert_eq!(glob("missing/*.md"), Vec::<String>::new());
+
+        let absolute = dir.path().join("work/*.md");
+        assert_eq!(
+            expand_glob(Path::new("/elsewhere"), &absolute.to_string_lossy()),
+            [dir.path().join("work/a.md")]
+        );
+    }
+}